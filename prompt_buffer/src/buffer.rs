@@ -9,16 +9,24 @@
 //! The base class
 use std::cmp;
 use std::env;
-use std::path::Path;
 use std::path::PathBuf;
 
-use self::lines::*;
-use line::{PromptBox, PromptLineBuilder, PromptLineType, PromptLines};
+use color::ColorSupport;
+use context::PromptContext;
+use line::{Addition, PromptBox, PromptLineBuilder, PromptLineType, PromptLines};
+use line_style::{LineStyle, BOTTOM, LEFT, RIGHT, TOP};
+use path::{self, DirSegment};
+use powerline;
 use shell::ShellType;
-use term::color;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use theme::Theme;
+use title;
+use width;
 
 /// Defines the speed at which to run the `to_string` method
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PluginSpeed {
     /// Don't run plugin
     Ignored,
@@ -36,11 +44,140 @@ impl PluginSpeed {
     }
 }
 
-mod lines {
-    pub const TOP: i16 = 8;
-    pub const BOTTOM: i16 = 4;
-    pub const LEFT: i16 = 2;
-    pub const RIGHT: i16 = 1;
+/// The ways a prompt can be drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Renderer {
+    /// A tree of box-drawing lines
+    #[default]
+    Boxes,
+
+    /// Colored segments joined by powerline arrows
+    Powerline,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Renderer, String> {
+        match s {
+            "boxes" => Ok(Renderer::Boxes),
+            "powerline" => Ok(Renderer::Powerline),
+            _ => Err(format!("unknown renderer `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Renderer::Boxes => "boxes",
+            Renderer::Powerline => "powerline",
+        })
+    }
+}
+
+/// What `PromptBuffer::convert_to_output` produces
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// A prompt for the shell
+    #[default]
+    Prompt,
+
+    /// The lines serialized as JSON, for other tools to draw
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "prompt" => Ok(Format::Prompt),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Format::Prompt => "prompt",
+            Format::Json => "json",
+        })
+    }
+}
+
+/// Settings that change how the prompt is drawn, but not what is in it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderOptions {
+    /// The colors the terminal is able to show
+    pub colors: ColorSupport,
+
+    /// How the lines are drawn
+    pub renderer: Renderer,
+
+    /// The characters used by the `Boxes` renderer
+    pub lines: LineStyle,
+
+    /// The width of the terminal, if it is known
+    pub columns: Option<usize>,
+
+    /// Whether to draw the prompt or serialize it
+    pub format: Format,
+
+    /// A built-in theme name or the path of a theme file. Uses the default
+    /// theme if not set
+    pub theme: Option<String>,
+
+    /// Template of the terminal title. The title is left alone if not set
+    pub title: Option<String>,
+}
+
+impl RenderOptions {
+    /// Detects the options for the terminal megaprompt is running in
+    pub fn from_env() -> RenderOptions {
+        RenderOptions {
+            colors: ColorSupport::from_env(),
+            lines: LineStyle::from_env(),
+            columns: width::terminal_columns(),
+            theme: Theme::spec_from_env(),
+            title: title::template_from_env(),
+            ..RenderOptions::default()
+        }
+    }
+}
+
+/// Separates the left and right prompt in `PromptOutput::encode`
+const SIDE_SEPARATOR: char = '\x1F';
+
+/// The rendered prompt
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct PromptOutput {
+    /// The tree shaped prompt
+    pub left: String,
+
+    /// The right aligned segments (RPROMPT / fish_right_prompt)
+    pub right: String,
+}
+
+impl PromptOutput {
+    /// Joins both sides with an ASCII unit separator
+    ///
+    /// Used for the daemon protocol, and by shells that split the output of
+    /// a single call into both prompts
+    pub fn encode(&self) -> String {
+        format!("{}{}{}", self.left, SIDE_SEPARATOR, self.right)
+    }
+
+    /// Reverses `encode`. Text without a separator is all left prompt
+    pub fn decode(s: &str) -> PromptOutput {
+        let mut parts = s.splitn(2, SIDE_SEPARATOR);
+        PromptOutput {
+            left: parts.next().unwrap_or("").to_owned(),
+            right: parts.next().unwrap_or("").to_owned(),
+        }
+    }
 }
 
 /// `PromptBuffer`
@@ -49,52 +186,102 @@ mod lines {
 /// Knows how to format a serise of `PromptLines` in a pretty way
 pub struct PromptBuffer {
     plugins: Vec<Box<dyn PromptBufferPlugin>>,
-    path: PathBuf,
-    shell: ShellType,
+    context: PromptContext,
+    options: RenderOptions,
+    theme: Arc<Theme>,
+
+    /// What each plugin drew in the last prompt
+    drawn: Vec<Addition>,
+}
+
+fn load_theme(spec: &Option<String>) -> Arc<Theme> {
+    match *spec {
+        Some(ref spec) => match Theme::load(spec) {
+            Ok(theme) => Arc::new(theme),
+            Err(e) => {
+                warn!("Using the default theme: {}", e);
+                Theme::shared_default()
+            }
+        },
+        None => Theme::shared_default(),
+    }
 }
 
 impl PromptBuffer {
     /// Construct a new default `PromptBuffer`
     pub fn new(shell: ShellType) -> PromptBuffer {
+        let options = RenderOptions::from_env();
         PromptBuffer {
-            shell,
             plugins: Vec::new(),
-            path: env::current_dir().unwrap_or_else(|_| PathBuf::new()),
+            context: PromptContext::new(
+                shell,
+                env::current_dir().unwrap_or_else(|_| PathBuf::new()),
+            ),
+            theme: load_theme(&options.theme),
+            options,
+            drawn: Vec::new(),
         }
     }
 
-    fn get_line(flags: i16) -> char {
-        match flags {
-            c if c == TOP | BOTTOM | LEFT | RIGHT => '┼',
-            c if c == TOP | BOTTOM | LEFT => '┤',
-            c if c == TOP | BOTTOM | RIGHT => '├',
-            c if c == TOP | BOTTOM => '│',
-            c if c == TOP | LEFT | RIGHT => '┴',
-            c if c == TOP | LEFT => '┘',
-            c if c == TOP | RIGHT => '└',
-            c if c == BOTTOM | LEFT => '┐',
-            c if c == BOTTOM | RIGHT => '┌',
-            c if c == BOTTOM | LEFT | RIGHT => '┬',
-            c if c == LEFT | RIGHT => '─',
-            _ => panic!("Passed invalid value to get_line"),
-        }
+    fn get_line(&self, flags: i16) -> char {
+        self.options.lines.get(flags)
     }
 
-    fn trail_off() -> String {
+    /// Fills the rest of a line that is `used` columns wide
+    fn trail_off(&self, used: usize) -> String {
+        let count = match self.options.columns {
+            Some(columns) => columns.saturating_sub(used),
+            None => 10,
+        };
+
         let mut retval = String::new();
-        for _ in 0..10 {
-            retval = format!("{}{}", retval, PromptBuffer::get_line(LEFT | RIGHT));
+        for _ in 0..count {
+            retval = format!("{}{}", retval, self.get_line(LEFT | RIGHT));
         }
         retval
     }
 
+    fn dir_segment(&self) -> DirSegment {
+        let home = self
+            .context
+            .var("HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(PathBuf::from));
+        DirSegment::new(&self.context.path, home.as_deref())
+    }
+
+    /// Returns the text and role of each box of the working directory
+    fn dir_parts(&self) -> Vec<(String, &'static str)> {
+        let mut dir = self.dir_segment();
+        dir.shorten(self.options.columns.map_or(40, |c| c / 2));
+
+        let mut parts = Vec::new();
+        if dir.in_repo {
+            parts.push((dir.root.clone(), "prompt.path.repo"));
+            if !dir.parts.is_empty() {
+                parts.push((dir.rest(), "prompt.path"));
+            }
+        } else {
+            parts.push((dir.plain(), "prompt.path"));
+        }
+
+        if !path::is_writable(&self.context.path) {
+            let readonly = match self.options.lines {
+                LineStyle::Ascii => "(ro)".to_owned(),
+                _ => self.theme.glyphs().readonly.clone(),
+            };
+            parts.push((readonly, "prompt.path.readonly"));
+        }
+        parts
+    }
+
     fn start(&self, lines: &mut PromptLines) {
-        lines.push(
-            PromptLineBuilder::new(self.shell)
-                .block(self.shell.dir())
-                .block(self.shell.hostname())
-                .build(),
-        );
+        let shell = self.context.shell;
+        let mut builder = PromptLineBuilder::new(shell);
+        for (text, role) in self.dir_parts() {
+            builder = builder.styled_block(text, self.theme.style(role));
+        }
+        lines.push(builder.build());
     }
 
     /// Adds a plugin to the prompt buffer
@@ -104,27 +291,90 @@ impl PromptBuffer {
         self.plugins.push(Box::new(plugin));
     }
 
+    /// Adds a plugin that was picked at run time, such as from a config file
+    pub fn add_boxed_plugin(&mut self, plugin: Box<dyn PromptBufferPlugin + Send>) {
+        self.plugins.push(plugin);
+    }
+
     /// Store the new path for the PromptBuffer.
     ///
     /// This is sent in as context to PromptBufferPlugins
     pub fn set_path(&mut self, p: PathBuf) {
-        self.path = p;
+        self.context.path = p;
     }
 
-    /// Returns the result of the prompt
+    /// Stores the context of the shell the prompt is drawn for
     ///
-    /// Allows specifying wanted plugin speed
-    pub fn convert_to_string_ext(&mut self, speed: PluginSpeed) -> String {
-        let mut retval = String::new();
-        let mut lines = Vec::new();
+    /// Also replaces the path, and the shell if it differs
+    pub fn set_context(&mut self, context: PromptContext) {
+        self.context = context;
+    }
 
-        self.start(&mut lines);
+    fn right(&self, lines: &PromptLines) -> String {
+        lines
+            .right()
+            .iter()
+            .map(|b| {
+                format!(
+                    "{}{}{}",
+                    self.get_line(LEFT | TOP | BOTTOM),
+                    b,
+                    self.get_line(TOP | BOTTOM | RIGHT)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(&self.get_line(LEFT | RIGHT).to_string())
+    }
 
-        if !speed.is_ignored() {
-            for p in &mut self.plugins {
-                p.run(speed, self.shell, &self.path, &mut lines);
+    /// Returns the columns a line takes up apart from its boxes, and the
+    /// columns each box adds around its text
+    fn overhead(&self, lines: &PromptLines, ix: usize) -> (usize, usize) {
+        let line = &lines[ix];
+        let per_box = match line.line_type {
+            PromptLineType::Boxed => 3,
+            PromptLineType::Free => 1,
+        };
+
+        match self.options.renderer {
+            Renderer::Boxes => {
+                let after = lines.get(ix + 1).map(|l| l.level).unwrap_or(0);
+                (usize::from(cmp::max(line.level, after)) + 1, per_box)
+            }
+            Renderer::Powerline => (2 * usize::from(line.level), per_box),
+        }
+    }
+
+    /// Ellipsizes the widest boxes of lines that don't fit in `columns`
+    fn fit(&self, lines: &mut PromptLines, columns: usize) {
+        let ellipsis = match self.options.lines {
+            LineStyle::Ascii => "...",
+            _ => &self.theme.glyphs().ellipsis,
+        };
+        let min_width = width::display_width(ellipsis) + 1;
+
+        for ix in 0..lines.len() {
+            let (fixed, per_box) = self.overhead(lines, ix);
+            let parts = &mut lines[ix].parts;
+            let available = columns.saturating_sub(fixed + per_box * parts.len());
+
+            loop {
+                let used = parts.iter().map(|b| b.width()).sum::<usize>();
+                let widest = match parts.iter_mut().max_by_key(|b| b.width()) {
+                    Some(b) if used > available && b.width() > min_width => b,
+                    _ => break,
+                };
+                let before = widest.width();
+                let target = cmp::max(before.saturating_sub(used - available), min_width);
+                widest.truncate(target, ellipsis);
+                if widest.width() >= before {
+                    break;
+                }
             }
         }
+    }
+
+    fn tree(&self, lines: &PromptLines) -> String {
+        let mut retval = String::new();
 
         for (ix, line) in lines.iter().enumerate() {
             let current = line.level;
@@ -145,7 +395,7 @@ impl PromptBuffer {
                 line_text = format!(
                     "{}{}",
                     line_text,
-                    PromptBuffer::get_line(
+                    self.get_line(
                         if i == current && ix > 0 { TOP } else { 0 }
                             | if i == after { BOTTOM } else { 0 }
                             | if i > start { LEFT } else { 0 }
@@ -167,36 +417,177 @@ impl PromptBuffer {
                     PromptLineType::Boxed => format!(
                         "{}{}{}{}{}",
                         line_text,
-                        PromptBuffer::get_line(LEFT | RIGHT),
-                        PromptBuffer::get_line(LEFT | TOP | BOTTOM),
+                        self.get_line(LEFT | RIGHT),
+                        self.get_line(LEFT | TOP | BOTTOM),
                         b,
-                        PromptBuffer::get_line(TOP | BOTTOM | RIGHT)
+                        self.get_line(TOP | BOTTOM | RIGHT)
                     ),
                     PromptLineType::Free => format!("{} {}", line_text, b),
                 };
             }
 
             if let PromptLineType::Boxed = line.line_type {
-                line_text = format!("{}{}", line_text, PromptBuffer::trail_off());
+                let used = line.parts.iter().map(|b| b.width() + 3).sum::<usize>();
+                line_text = format!(
+                    "{}{}",
+                    line_text,
+                    self.trail_off(usize::from(end) + 1 + used)
+                );
             }
 
-            retval = format!("{}{}\n", retval, line_text);
+            retval = format!("{}{}{}", retval, line_text, self.context.shell.newline());
         }
 
         format!(
             "{}{}{}{} ",
             retval,
-            PromptBuffer::get_line(TOP | RIGHT),
-            PromptBuffer::get_line(LEFT | RIGHT),
-            PromptBox::new(
-                self.shell.dollar().to_owned(),
-                color::RED,
-                false,
-                self.shell
-            )
+            self.get_line(TOP | RIGHT),
+            self.get_line(LEFT | RIGHT),
+            self.dollar()
         )
     }
 
+    fn dollar(&self) -> PromptBox {
+        let role = match self.context.state.exit_status {
+            Some(0) => "prompt.char.success",
+            Some(_) => "prompt.char.failure",
+            None => "prompt.char",
+        };
+        PromptBox::raw(
+            self.context.shell.dollar(),
+            self.theme.style(role),
+            self.context.shell,
+        )
+    }
+
+    /// Returns the lines every prompt starts with, before the plugins run
+    fn begin(&self) -> PromptLines {
+        let mut lines = PromptLines::with_theme(self.theme.clone());
+        lines.set_title_field("dir", self.dir_segment().plain());
+        lines.set_title_field("host", self.context.shell.expanded_hostname());
+        if let Some(ref user) = self.context.user {
+            lines.set_title_field("user", user);
+        }
+
+        self.start(&mut lines);
+        lines
+    }
+
+    /// Returns both sides of the prompt
+    ///
+    /// Allows specifying wanted plugin speed
+    pub fn convert_to_output(&mut self, speed: PluginSpeed) -> PromptOutput {
+        let mut lines = self.begin();
+
+        self.drawn.clear();
+        if !speed.is_ignored() {
+            self.context.speed = speed;
+            self.context.columns = self.options.columns;
+            for p in &mut self.plugins {
+                let mark = lines.mark();
+                p.run(&self.context, &mut lines);
+                self.drawn.push(lines.since(&mark));
+            }
+        }
+
+        self.render(lines)
+    }
+
+    /// Returns what each plugin drew in the last prompt
+    pub(crate) fn drawn(&self) -> &[Addition] {
+        &self.drawn
+    }
+
+    /// Draws the prompt again from what the plugins drew in an earlier one
+    ///
+    /// Only the plugins that show the state of the last command are run, so
+    /// the prompt is as fast as without slow plugins but still shows them
+    pub(crate) fn redraw(&mut self, drawn: &[Addition]) -> PromptOutput {
+        if drawn.len() != self.plugins.len() {
+            return self.convert_to_output(PluginSpeed::Fast);
+        }
+
+        let mut lines = self.begin();
+        self.context.speed = PluginSpeed::Fast;
+        self.context.columns = self.options.columns;
+        for (p, addition) in self.plugins.iter_mut().zip(drawn) {
+            if p.uses_state() {
+                p.run(&self.context, &mut lines);
+            } else {
+                lines.add(addition);
+            }
+        }
+
+        self.render(lines)
+    }
+
+    fn render(&self, mut lines: PromptLines) -> PromptOutput {
+        if let Format::Json = self.options.format {
+            return PromptOutput {
+                left: lines.to_json(),
+                right: String::new(),
+            };
+        }
+
+        for b in lines.boxes_mut() {
+            b.downgrade(self.options.colors);
+        }
+
+        if let Some(columns) = self.options.columns {
+            self.fit(&mut lines, columns);
+        }
+
+        let (left, right) = match self.options.renderer {
+            Renderer::Boxes => (self.tree(&lines), self.right(&lines)),
+            Renderer::Powerline => (
+                powerline::left(self.context.shell, &lines, &self.dollar()),
+                powerline::right(self.context.shell, &lines),
+            ),
+        };
+
+        let title = match self.options.title {
+            Some(ref template) => self
+                .context
+                .shell
+                .title(&title::render(template, lines.title_fields())),
+            None => String::new(),
+        };
+
+        PromptOutput {
+            left: format!("{}{}{}", self.context.shell.prompt_start(), title, left),
+            right,
+        }
+    }
+
+    /// Changes how the prompt is drawn
+    pub fn set_render_options(&mut self, options: RenderOptions) {
+        if options.theme != self.options.theme {
+            self.theme = load_theme(&options.theme);
+        }
+        self.options = options;
+    }
+
+    /// Returns the single line a prompt collapses to once its command has
+    /// been submitted
+    ///
+    /// No plugins are run
+    pub fn convert_to_compact_string(&self) -> String {
+        let shell = self.context.shell;
+        let dir = self
+            .dir_parts()
+            .into_iter()
+            .map(|(text, role)| PromptBox::styled(text, self.theme.style(role), shell).to_string())
+            .collect::<String>();
+        format!("{}{} {} ", shell.prompt_start(), dir, self.dollar())
+    }
+
+    /// Returns the result of the prompt
+    ///
+    /// Allows specifying wanted plugin speed
+    pub fn convert_to_string_ext(&mut self, speed: PluginSpeed) -> String {
+        self.convert_to_output(speed).left
+    }
+
     /// Returns the prompt with plugins run
     pub fn convert_to_string(&mut self) -> String {
         self.convert_to_string_ext(PluginSpeed::Slow)
@@ -204,12 +595,12 @@ impl PromptBuffer {
 
     /// Print a result with the plugins
     pub fn print(&mut self) {
-        println!("{}", self.convert_to_string());
+        print!("{}", self.convert_to_string());
     }
 
     /// Print a result while skipping all plugins
     pub fn print_fast(&mut self) {
-        println!("{}", self.convert_to_string_ext(PluginSpeed::Fast));
+        print!("{}", self.convert_to_string_ext(PluginSpeed::Fast));
     }
 }
 
@@ -217,6 +608,164 @@ impl PromptBuffer {
 pub trait PromptBufferPlugin: Send {
     /// Should append as many PromptLines as it wants to the lines Vec
     ///
-    /// The path can be used to provide context if necessary
-    fn run(&mut self, speed: PluginSpeed, shell: ShellType, path: &Path, lines: &mut PromptLines);
+    /// Segments for the right prompt can be added with `lines.push_right`
+    ///
+    /// The context tells where and for whom the prompt is drawn, and how
+    /// fast the plugin needs to be
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines);
+
+    /// Whether the plugin shows the state of the last command, such as its
+    /// exit status
+    ///
+    /// When a prompt is drawn again from what the plugins drew for an
+    /// earlier command, only these plugins are run again
+    fn uses_state(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    struct RightPlugin;
+
+    impl PromptBufferPlugin for RightPlugin {
+        fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+            let shell = context.shell;
+            lines.push_right(shell.new_line().block("a").block("b").build());
+        }
+    }
+
+    #[test]
+    fn right_prompt_is_kept_out_of_the_tree() {
+        let mut buf = PromptBuffer::new(ShellType::Fish);
+        buf.set_render_options(RenderOptions::default());
+        buf.add_plugin(RightPlugin);
+
+        let output = buf.convert_to_output(PluginSpeed::Slow);
+        assert_eq!(output.left.lines().count(), 2);
+        assert_eq!(
+            output.right,
+            "┤\x1B[35ma\x1B[0m├─┤\x1B[35mb\x1B[0m├".to_owned()
+        );
+    }
+
+    struct LongPlugin;
+
+    impl PromptBufferPlugin for LongPlugin {
+        fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+            let shell = context.shell;
+            lines.push(shell.new_line().block("x".repeat(100)).build());
+            lines.push(
+                shell
+                    .new_free_line()
+                    .indent()
+                    .block("日".repeat(100))
+                    .build(),
+            );
+        }
+    }
+
+    #[test]
+    fn lines_fit_the_terminal() {
+        let mut buf = PromptBuffer::new(ShellType::Fish);
+        buf.set_render_options(RenderOptions {
+            columns: Some(40),
+            ..RenderOptions::default()
+        });
+        buf.add_plugin(LongPlugin);
+
+        let output = buf.convert_to_output(PluginSpeed::Slow);
+        let widths = output
+            .left
+            .lines()
+            .map(width::display_width)
+            .collect::<Vec<_>>();
+        assert_eq!(widths[..2], [40, 40]);
+        assert!(widths[2] <= 40);
+        assert!(output.left.contains("xxx…"));
+    }
+
+    struct ManyPlugin;
+
+    impl PromptBufferPlugin for ManyPlugin {
+        fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+            let shell = context.shell;
+            let mut line = shell.new_line();
+            for _ in 0..5 {
+                line = line.block("xxxxxx");
+            }
+            lines.push(line.build());
+        }
+    }
+
+    #[test]
+    fn very_narrow_terminals_get_what_fits() {
+        for &columns in &[0, 1, 8] {
+            let mut buf = PromptBuffer::new(ShellType::Fish);
+            buf.set_render_options(RenderOptions {
+                columns: Some(columns),
+                ..RenderOptions::default()
+            });
+            buf.add_plugin(LongPlugin);
+            buf.add_plugin(ManyPlugin);
+
+            let output = buf.convert_to_output(PluginSpeed::Slow);
+            assert!(output.left.contains("x…"));
+            assert!(!output.left.contains("xxxxxx"));
+        }
+    }
+
+    #[test]
+    fn compact_prompt_is_one_line() {
+        let repo = env::temp_dir()
+            .join(format!("megaprompt-path-test-{}", ::std::process::id()))
+            .join("re$po");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(repo.join("src")).unwrap();
+
+        let mut buf = PromptBuffer::new(ShellType::Bash);
+        buf.set_path(repo.join("src"));
+        buf.add_plugin(RightPlugin);
+
+        let compact = buf.convert_to_compact_string();
+        fs::remove_dir_all(repo.parent().unwrap()).unwrap();
+        assert_eq!(
+            compact,
+            "\\[\x1B[1;35m\\]re\\\\$po\\[\x1B[0m\\]\\[\x1B[35m\\]/src\\[\x1B[0m\\] \\[\x1B[31m\\]\\$\\[\x1B[0m\\] "
+        );
+    }
+
+    struct TitlePlugin;
+
+    impl PromptBufferPlugin for TitlePlugin {
+        fn run(&mut self, _: &PromptContext, lines: &mut PromptLines) {
+            lines.set_title_field("repo", "50%");
+        }
+    }
+
+    #[test]
+    fn title_is_hidden() {
+        let mut buf = PromptBuffer::new(ShellType::Zsh);
+        buf.set_render_options(RenderOptions {
+            title: Some("[{repo} ]{nope}".to_owned()),
+            ..RenderOptions::default()
+        });
+        buf.add_plugin(TitlePlugin);
+
+        let output = buf.convert_to_output(PluginSpeed::Slow);
+        assert!(output.left.starts_with("%{\x1B]0;50%% \x07%}"));
+    }
+
+    #[test]
+    fn output_round_trips() {
+        let output = PromptOutput {
+            left: "left\n$ ".to_owned(),
+            right: "right".to_owned(),
+        };
+        assert_eq!(PromptOutput::decode(&output.encode()), output);
+        assert_eq!(PromptOutput::decode("only left").right, "");
+    }
 }
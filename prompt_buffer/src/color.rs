@@ -0,0 +1,365 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Colors for prompt boxes, and how to downgrade them for terminals that
+//! can't show them
+
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+/// A terminal color
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    /// One of the 16 standard colors. 0-7 are normal, 8-15 are bright.
+    /// Anything above is drawn as the palette index it is
+    Named(u8),
+
+    /// An index into the 256 color palette
+    Fixed(u8),
+
+    /// A 24 bit `#rrggbb` color
+    Rgb(u8, u8, u8),
+}
+
+/// Black
+pub const BLACK: Color = Color::Named(0);
+/// Red
+pub const RED: Color = Color::Named(1);
+/// Green
+pub const GREEN: Color = Color::Named(2);
+/// Yellow
+pub const YELLOW: Color = Color::Named(3);
+/// Blue
+pub const BLUE: Color = Color::Named(4);
+/// Magenta
+pub const MAGENTA: Color = Color::Named(5);
+/// Cyan
+pub const CYAN: Color = Color::Named(6);
+/// White
+pub const WHITE: Color = Color::Named(7);
+/// Bright black (grey)
+pub const BRIGHT_BLACK: Color = Color::Named(8);
+/// Bright red
+pub const BRIGHT_RED: Color = Color::Named(9);
+/// Bright green
+pub const BRIGHT_GREEN: Color = Color::Named(10);
+/// Bright yellow
+pub const BRIGHT_YELLOW: Color = Color::Named(11);
+/// Bright blue
+pub const BRIGHT_BLUE: Color = Color::Named(12);
+/// Bright magenta
+pub const BRIGHT_MAGENTA: Color = Color::Named(13);
+/// Bright cyan
+pub const BRIGHT_CYAN: Color = Color::Named(14);
+/// Bright white
+pub const BRIGHT_WHITE: Color = Color::Named(15);
+
+const NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright-black",
+    "bright-red",
+    "bright-green",
+    "bright-yellow",
+    "bright-blue",
+    "bright-magenta",
+    "bright-cyan",
+    "bright-white",
+];
+
+// xterm's default values for the 16 named colors
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn cube_index(v: u8) -> u8 {
+    CUBE_LEVELS
+        .iter()
+        .enumerate()
+        .min_by_key(|&(_, &l)| (i32::from(l) - i32::from(v)).abs())
+        .map(|(i, _)| i as u8)
+        .unwrap_or(0)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> i32 {
+    let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+impl Color {
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Named(n) | Color::Fixed(n) if n < 16 => PALETTE[usize::from(n)],
+            Color::Named(n) => Color::Fixed(n).rgb(),
+            Color::Fixed(n) if n < 232 => {
+                let n = n - 16;
+                (
+                    CUBE_LEVELS[usize::from(n / 36)],
+                    CUBE_LEVELS[usize::from(n / 6 % 6)],
+                    CUBE_LEVELS[usize::from(n % 6)],
+                )
+            }
+            Color::Fixed(n) => {
+                let v = 8 + (n - 232) * 10;
+                (v, v, v)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    fn to_fixed(self) -> Color {
+        let (r, g, b) = self.rgb();
+        let cube = 16 + 36 * cube_index(r) + 6 * cube_index(g) + cube_index(b);
+        let grey_level = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+        let grey = 232 + ((grey_level.saturating_sub(3)) / 10).min(23) as u8;
+
+        if distance(Color::Fixed(grey).rgb(), (r, g, b))
+            < distance(Color::Fixed(cube).rgb(), (r, g, b))
+        {
+            Color::Fixed(grey)
+        } else {
+            Color::Fixed(cube)
+        }
+    }
+
+    fn to_named(self) -> Color {
+        let rgb = self.rgb();
+        let closest = PALETTE
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &p)| distance(p, rgb))
+            .map(|(i, _)| i as u8)
+            .unwrap_or(7);
+        Color::Named(closest)
+    }
+
+    /// Returns the closest color the terminal is able to show
+    pub fn downgrade(self, support: ColorSupport) -> Color {
+        match (self, support) {
+            (Color::Named(n), _) if n < 16 => self,
+            (Color::Named(n), _) => Color::Fixed(n).downgrade(support),
+            (_, ColorSupport::TrueColor) => self,
+            (Color::Fixed(n), _) if n < 16 => Color::Named(n),
+            (Color::Fixed(_), ColorSupport::Ansi256) => self,
+            (Color::Rgb(..), ColorSupport::Ansi256) => self.to_fixed(),
+            (_, ColorSupport::Ansi16) => self.to_named(),
+        }
+    }
+
+    /// Returns the SGR parameters that select this color
+    pub fn sgr(self, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        match self {
+            Color::Named(n) if n < 8 => format!("{}", base + u32::from(n)),
+            Color::Named(n) if n < 16 => format!("{}", base + 60 + u32::from(n - 8)),
+            Color::Named(n) | Color::Fixed(n) => format!("{};5;{}", base + 8, n),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
+}
+
+impl TryFrom<u32> for Color {
+    type Error = String;
+
+    /// Converts a `term::color::Color` style number, which has to be in the
+    /// 256 color palette
+    fn try_from(c: u32) -> Result<Color, String> {
+        match u8::try_from(c) {
+            Ok(n) if n < 16 => Ok(Color::Named(n)),
+            Ok(n) => Ok(Color::Fixed(n)),
+            Err(_) => Err(format!("invalid color `{}`", c)),
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parses a color name (`red`, `bright-blue`), a palette index (`208`)
+    /// or a `#rrggbb` color
+    fn from_str(s: &str) -> Result<Color, String> {
+        let name = s.trim().to_lowercase().replace('_', "-");
+        if let Some(ix) = NAMES.iter().position(|n| *n == name) {
+            return Ok(Color::Named(ix as u8));
+        }
+
+        if name.starts_with('#') && name.len() == 7 {
+            let channel = |i: usize| u8::from_str_radix(&name[i..i + 2], 16);
+            if let (Ok(r), Ok(g), Ok(b)) = (channel(1), channel(3), channel(5)) {
+                return Ok(Color::Rgb(r, g, b));
+            }
+        }
+
+        name.parse()
+            .map(Color::Fixed)
+            .map_err(|_| format!("invalid color `{}`", s))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Color::Named(n) if n < 16 => write!(f, "{}", NAMES[usize::from(n)]),
+            Color::Named(n) | Color::Fixed(n) => write!(f, "{}", n),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+/// How many colors a terminal is able to show
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ColorSupport {
+    /// Only the 16 named colors
+    #[default]
+    Ansi16,
+
+    /// The 256 color palette
+    Ansi256,
+
+    /// 24 bit colors
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Guesses the support of the current terminal from `COLORTERM` and
+    /// `TERM`
+    pub fn from_env() -> ColorSupport {
+        ColorSupport::from_vars(
+            env::var("COLORTERM").ok().as_ref().map(|s| s.as_ref()),
+            env::var("TERM").ok().as_ref().map(|s| s.as_ref()),
+        )
+    }
+
+    /// Guesses the support from the values of `COLORTERM` and `TERM`
+    pub fn from_vars(colorterm: Option<&str>, term: Option<&str>) -> ColorSupport {
+        let term = term.unwrap_or("");
+        match colorterm {
+            Some("truecolor") | Some("24bit") => ColorSupport::TrueColor,
+            _ if term.ends_with("-direct") => ColorSupport::TrueColor,
+            _ if term.contains("256color") => ColorSupport::Ansi256,
+            _ => ColorSupport::Ansi16,
+        }
+    }
+}
+
+impl FromStr for ColorSupport {
+    type Err = String;
+
+    /// Parses `16`, `256` or `truecolor`
+    fn from_str(s: &str) -> Result<ColorSupport, String> {
+        match s {
+            "16" => Ok(ColorSupport::Ansi16),
+            "256" => Ok(ColorSupport::Ansi256),
+            "truecolor" | "24bit" => Ok(ColorSupport::TrueColor),
+            _ => Err(format!("invalid color support `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColorSupport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ColorSupport::Ansi16 => "16",
+            ColorSupport::Ansi256 => "256",
+            ColorSupport::TrueColor => "truecolor",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!("red".parse(), Ok(RED));
+        assert_eq!("Bright_Blue".parse(), Ok(BRIGHT_BLUE));
+        assert_eq!("208".parse(), Ok(Color::Fixed(208)));
+        assert_eq!("#ff8700".parse(), Ok(Color::Rgb(0xff, 0x87, 0x00)));
+        assert!("#ff87".parse::<Color>().is_err());
+        assert!("mauve".parse::<Color>().is_err());
+        assert!("256".parse::<Color>().is_err());
+        assert_eq!(Color::try_from(9), Ok(BRIGHT_RED));
+        assert_eq!(Color::try_from(208), Ok(Color::Fixed(208)));
+        assert!(Color::try_from(300).is_err());
+    }
+
+    #[test]
+    fn emits_sgr() {
+        assert_eq!(RED.sgr(false), "31");
+        assert_eq!(BRIGHT_RED.sgr(false), "91");
+        assert_eq!(BRIGHT_RED.sgr(true), "101");
+        assert_eq!(Color::Fixed(208).sgr(false), "38;5;208");
+        assert_eq!(Color::Rgb(1, 2, 3).sgr(true), "48;2;1;2;3");
+        // Past the 16 names, they are palette indices
+        assert_eq!(Color::Named(17).sgr(false), "38;5;17");
+    }
+
+    #[test]
+    fn downgrades() {
+        let orange = Color::Rgb(0xff, 0x87, 0x00);
+        assert_eq!(orange.downgrade(ColorSupport::TrueColor), orange);
+        assert_eq!(orange.downgrade(ColorSupport::Ansi256), Color::Fixed(208));
+        assert_eq!(
+            Color::Rgb(0x30, 0x30, 0x30).downgrade(ColorSupport::Ansi256),
+            Color::Fixed(236)
+        );
+        assert_eq!(
+            Color::Fixed(196).downgrade(ColorSupport::Ansi16),
+            BRIGHT_RED
+        );
+        assert_eq!(Color::Fixed(3).downgrade(ColorSupport::Ansi16), YELLOW);
+        assert_eq!(CYAN.downgrade(ColorSupport::Ansi16), CYAN);
+    }
+
+    #[test]
+    fn detects_support() {
+        assert_eq!(
+            ColorSupport::from_vars(Some("truecolor"), Some("xterm")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_vars(None, Some("xterm-256color")),
+            ColorSupport::Ansi256
+        );
+        assert_eq!(
+            ColorSupport::from_vars(None, Some("xterm-direct")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_vars(None, Some("vt100")),
+            ColorSupport::Ansi16
+        );
+        assert_eq!(ColorSupport::from_vars(None, None), ColorSupport::Ansi16);
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Everything plugins get to know about the prompt they add to
+//!
+//! The context is captured by the client, inside the user's shell, and sent
+//! along to the daemon. The daemon's own environment, user and working
+//! directory say nothing about the shell the prompt is for.
+
+use buffer::PluginSpeed;
+use libc;
+use shell::ShellType;
+use state::ShellState;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::CStr;
+use std::path::PathBuf;
+
+/// Returns the name of the user logged in on the controlling terminal
+fn login_name() -> Option<String> {
+    let name = unsafe { libc::getlogin() };
+    if name.is_null() {
+        return None;
+    }
+
+    let name = unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned();
+    Some(name).filter(|n| !n.is_empty())
+}
+
+/// Where and for whom a prompt is drawn
+#[derive(Clone, Debug, PartialEq)]
+pub struct PromptContext {
+    /// How much time plugins are allowed to take
+    pub speed: PluginSpeed,
+
+    /// The shell the prompt is for
+    pub shell: ShellType,
+
+    /// The working directory of the shell
+    pub path: PathBuf,
+
+    /// What the shell reported about the last command
+    pub state: ShellState,
+
+    /// The environment of the client, which inherits the shell's exported
+    /// variables
+    pub env: HashMap<String, String>,
+
+    /// Name of the user the shell runs as
+    pub user: Option<String>,
+
+    /// Name of the user logged in on the terminal, which differs from `user`
+    /// after `su`
+    pub login: Option<String>,
+
+    /// Effective user id of the shell
+    pub uid: Option<u32>,
+
+    /// Process id of the shell, if it passed it along. The client runs in a
+    /// subshell of its own, so its parent isn't the shell
+    pub pid: Option<u32>,
+
+    /// Width of the terminal, if it is known
+    pub columns: Option<usize>,
+}
+
+impl PromptContext {
+    /// Creates a context that knows nothing but the shell and its directory
+    pub fn new(shell: ShellType, path: PathBuf) -> PromptContext {
+        PromptContext {
+            speed: PluginSpeed::Slow,
+            shell,
+            path,
+            state: ShellState::default(),
+            env: HashMap::new(),
+            user: None,
+            login: None,
+            uid: None,
+            pid: None,
+            columns: None,
+        }
+    }
+
+    /// Captures the context of the process megaprompt was started from
+    pub fn from_env(shell: ShellType) -> PromptContext {
+        let env = env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .collect::<HashMap<_, _>>();
+        let user = env.get("USER").or_else(|| env.get("LOGNAME")).cloned();
+
+        PromptContext {
+            env,
+            user,
+            login: login_name(),
+            uid: Some(unsafe { libc::geteuid() }),
+            ..PromptContext::new(shell, env::current_dir().unwrap_or_default())
+        }
+    }
+
+    /// Returns the value of an environment variable of the shell
+    pub fn var(&self, key: &str) -> Option<&str> {
+        self.env.get(key).map(|v| v.as_str())
+    }
+}
@@ -7,14 +7,15 @@
 // except according to those terms.
 
 //! This module contains error object for `PromptBuffer`
-use std::io;
 use std::convert;
+use std::io;
 use std::sync::mpsc;
 
 /// Convenience wrapper for `Result<T, PromptBufferError>`
 pub type PromptBufferResult<T> = Result<T, PromptBufferError>;
 
 /// The base error type of `PromptBuffer`
+#[derive(Debug)]
 pub enum PromptBufferError {
     /// Error variant for IO errors
     IO(io::Error),
@@ -0,0 +1,93 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serializes `PromptLines` for tools that draw the prompt data themselves
+//!
+//! ```json
+//! {
+//!   "lines": [
+//!     {
+//!       "level": 0,
+//!       "type": "boxed",
+//!       "boxes": [
+//!         {
+//!           "text": "~/src",
+//!           "color": "magenta",
+//!           "background": null,
+//!           "bold": false,
+//!           "link": null
+//!         }
+//!       ]
+//!     }
+//!   ],
+//!   "right": []
+//! }
+//! ```
+//!
+//! Box text is what the shell would show: escapes are expanded where
+//! megaprompt knows their value, and color sequences are dropped.
+
+use line::{PromptBox, PromptLineType, PromptLines};
+use serde_json;
+
+#[derive(Serialize)]
+struct JsonBox {
+    text: String,
+    color: Option<String>,
+    background: Option<String>,
+    bold: bool,
+    link: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonLine {
+    level: u8,
+    #[serde(rename = "type")]
+    line_type: &'static str,
+    boxes: Vec<JsonBox>,
+}
+
+#[derive(Serialize)]
+struct JsonPrompt {
+    lines: Vec<JsonLine>,
+    right: Vec<JsonBox>,
+}
+
+fn json_box(b: &PromptBox) -> JsonBox {
+    JsonBox {
+        text: b.plain_text(),
+        color: b.color().map(|c| format!("{}", c)),
+        background: b.background().map(|c| format!("{}", c)),
+        bold: b.is_bold(),
+        link: b.link().map(|l| l.to_owned()),
+    }
+}
+
+pub(crate) fn render(lines: &PromptLines, pretty: bool) -> String {
+    let prompt = JsonPrompt {
+        lines: lines
+            .iter()
+            .map(|l| JsonLine {
+                level: l.level,
+                line_type: match l.line_type {
+                    PromptLineType::Boxed => "boxed",
+                    PromptLineType::Free => "free",
+                },
+                boxes: l.parts.iter().map(json_box).collect(),
+            })
+            .collect(),
+        right: lines.right().iter().map(json_box).collect(),
+    };
+
+    if pretty {
+        serde_json::to_string_pretty(&prompt)
+    } else {
+        serde_json::to_string(&prompt)
+    }
+    .expect("Unable to serialize the prompt")
+}
@@ -15,17 +15,38 @@
 
 #[macro_use]
 extern crate chan;
+extern crate libc;
 #[macro_use]
 extern crate log;
-extern crate term;
+#[macro_use]
+extern crate serde;
+extern crate serde_json;
+extern crate toml;
+extern crate unicode_width;
 
 mod buffer;
+mod context;
 mod thread;
+mod width;
 mod line;
+mod line_style;
 mod error;
+mod json;
+mod path;
 mod shell;
+mod state;
+mod theme;
+mod title;
+mod powerline;
+pub mod color;
+pub mod link;
 
-pub use buffer::{PluginSpeed, PromptBuffer, PromptBufferPlugin};
+pub use buffer::{PluginSpeed, PromptBuffer, PromptBufferPlugin, PromptOutput, RenderOptions,
+                 Renderer, Format};
+pub use context::PromptContext;
 pub use shell::ShellType;
+pub use state::ShellState;
+pub use theme::{Glyphs, Style, Theme};
 pub use thread::PromptThread;
 pub use line::PromptLines;
+pub use line_style::LineStyle;
@@ -8,9 +8,15 @@
 
 //! Utilities and tools for drawing prompt lines
 
+use color::{self, Color, ColorSupport};
+use json;
 use shell::ShellType;
+use std::collections::HashMap;
 use std::fmt;
-use term::color;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use theme::{Style, Theme};
+use width;
 
 /// The possible types for prompt lines
 #[derive(Clone, Copy)]
@@ -26,23 +32,194 @@ pub enum PromptLineType {
 ///
 /// The smallest component of a prompt line
 ///
-/// Contains a color, text, and "is bold" flag
+/// Contains a color, an optional background, text, and "is bold" flag
+///
+/// The text is escaped for the shell when it is drawn, so branch names and
+/// file names show up as they are. Only raw boxes may contain the shell's own
+/// prompt escapes, like bash's `\w`.
 #[derive(Clone)]
 pub struct PromptBox {
-    color: color::Color,
+    color: Option<Color>,
+    background: Option<Color>,
     text: String,
+    expanded: Option<String>,
+    link: Option<String>,
     is_bold: bool,
+    is_raw: bool,
     shell: ShellType,
+
+    /// Text drawn after `text` in the same box, each piece in a style of its
+    /// own
+    tail: Vec<(String, Style)>,
 }
 
 impl PromptBox {
     /// Creates a prompt box
-    pub fn new(text: String, color: color::Color, is_bold: bool, shell: ShellType) -> PromptBox {
+    pub fn new(text: String, color: Color, is_bold: bool, shell: ShellType) -> PromptBox {
+        PromptBox::styled(
+            text,
+            Style {
+                fg: Some(color),
+                bg: None,
+                bold: is_bold,
+            },
+            shell,
+        )
+    }
+
+    /// Creates a prompt box drawn in a theme style
+    pub fn styled(text: String, style: Style, shell: ShellType) -> PromptBox {
         PromptBox {
-            color,
+            color: style.fg,
+            background: style.bg,
             text,
-            is_bold,
+            expanded: None,
+            link: None,
+            is_bold: style.bold,
+            is_raw: false,
             shell,
+            tail: Vec::new(),
+        }
+    }
+
+    /// Creates a prompt box of text the shell interprets, such as its own
+    /// prompt escapes
+    ///
+    /// Anything in it that comes from outside megaprompt must be passed
+    /// through `ShellType::escape` first
+    pub fn raw(text: String, style: Style, shell: ShellType) -> PromptBox {
+        PromptBox {
+            is_raw: true,
+            ..PromptBox::styled(text, style, shell)
+        }
+    }
+
+    /// Returns the text color of the box, if it has one
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    /// Returns whether the box is bold
+    pub fn is_bold(&self) -> bool {
+        self.is_bold
+    }
+
+    /// Returns the text as the shell shows it, without escape sequences
+    pub fn plain_text(&self) -> String {
+        let mut text = width::plain_text(self.expanded.as_ref().unwrap_or(&self.text));
+        for (piece, _) in &self.tail {
+            text.push_str(piece);
+        }
+        text
+    }
+
+    /// Returns the background color of the box, if it has one
+    pub fn background(&self) -> Option<Color> {
+        self.background
+    }
+
+    /// Sets the background color of the box
+    pub fn set_background(&mut self, background: Color) {
+        self.background = Some(background);
+    }
+
+    /// Sets the text the shell shows in place of the escapes in the box
+    ///
+    /// Used to measure boxes such as bash's `\w`
+    pub fn set_expanded(&mut self, expanded: String) {
+        self.expanded = Some(expanded);
+    }
+
+    /// Returns where the box links to, if anywhere
+    pub fn link(&self) -> Option<&str> {
+        self.link.as_deref()
+    }
+
+    /// Makes the text of the box a hyperlink to `url`
+    pub fn set_link(&mut self, url: String) {
+        self.link = Some(url);
+    }
+
+    /// Adds text in another style after the text of the box
+    pub fn append(&mut self, text: String, style: Style) {
+        self.tail.push((text, style));
+    }
+
+    /// Returns the text escaped for the shell, wrapped in the escapes for its
+    /// link if it has one, followed by the tail of the box
+    fn linked_text(&self, background: Option<Color>) -> String {
+        let text = if self.is_raw {
+            self.text.clone()
+        } else {
+            self.shell.escape(&self.text)
+        };
+        let text = match self.link {
+            Some(ref url) => format!(
+                "{}{}{}",
+                self.shell.link_start(url),
+                text,
+                self.shell.link_end()
+            ),
+            None => text,
+        };
+
+        // The style is only reset when the piece before had one
+        let mut retval = text;
+        let mut styled = self.color.is_some() || background.is_some() || self.is_bold;
+        for &(ref piece, style) in &self.tail {
+            let bg = style.bg.or(background);
+            if styled {
+                retval.push_str(&self.shell.reset());
+            }
+            styled = style.fg.is_some() || bg.is_some() || style.bold;
+            if styled {
+                retval.push_str(&self.shell.style(style.fg, bg, style.bold));
+            }
+            retval.push_str(&self.shell.escape(piece));
+        }
+        retval
+    }
+
+    /// Returns the number of columns the text of the box takes up
+    pub(crate) fn width(&self) -> usize {
+        let tail = self
+            .tail
+            .iter()
+            .map(|(piece, _)| width::display_width(piece))
+            .sum::<usize>();
+        width::display_width(self.expanded.as_ref().unwrap_or(&self.text)) + tail
+    }
+
+    /// Shortens the text of the box to `columns`
+    ///
+    /// Boxes made of shell escapes are replaced by their shortened expansion
+    pub(crate) fn truncate(&mut self, columns: usize, ellipsis: &str) {
+        if let Some(expanded) = self.expanded.take() {
+            self.text = expanded;
+        }
+        for (piece, _) in self.tail.drain(..) {
+            self.text.push_str(&piece);
+        }
+        self.text = width::truncate(&self.text, columns, ellipsis);
+        self.is_raw = false;
+    }
+
+    /// Returns the box drawn on top of `background` without resetting the
+    /// style afterwards, padded with a space on either side
+    pub(crate) fn segment(&self, background: Color) -> String {
+        format!(
+            "{} {} ",
+            self.shell.style(self.color, Some(background), self.is_bold),
+            self.linked_text(Some(background))
+        )
+    }
+
+    pub(crate) fn downgrade(&mut self, support: ColorSupport) {
+        self.color = self.color.map(|c| c.downgrade(support));
+        self.background = self.background.map(|c| c.downgrade(support));
+        for &mut (_, ref mut style) in &mut self.tail {
+            style.fg = style.fg.map(|c| c.downgrade(support));
+            style.bg = style.bg.map(|c| c.downgrade(support));
         }
     }
 }
@@ -52,12 +229,8 @@ impl fmt::Display for PromptBox {
         write!(
             f,
             "{}{}{}",
-            if self.is_bold {
-                self.shell.bcol(self.color)
-            } else {
-                self.shell.col(self.color)
-            },
-            self.text,
+            self.shell.style(self.color, self.background, self.is_bold),
+            self.linked_text(self.background),
             self.shell.reset()
         )
     }
@@ -97,7 +270,167 @@ impl PromptLine {
 }
 
 /// A list of `PromptLines`
-pub type PromptLines = Vec<PromptLine>;
+///
+/// Derefs to the lines drawn as a tree. Segments meant for the right side
+/// of the prompt are kept separately. Also carries the theme, so plugins can
+/// look up the styles of their roles, and the fields of the terminal title.
+#[derive(Clone)]
+pub struct PromptLines {
+    lines: Vec<PromptLine>,
+    right: Vec<PromptBox>,
+    theme: Arc<Theme>,
+    title: HashMap<String, String>,
+}
+
+/// Where `PromptLines` ended at some point
+pub(crate) struct LinesMark {
+    lines: usize,
+    first: usize,
+    right: usize,
+    title: HashMap<String, String>,
+}
+
+/// What was added to `PromptLines`, such as by a single plugin
+#[derive(Clone)]
+pub(crate) struct Addition {
+    first: Vec<PromptBox>,
+    lines: Vec<PromptLine>,
+    right: Vec<PromptBox>,
+    title: Vec<(String, String)>,
+}
+
+impl Default for PromptLines {
+    fn default() -> PromptLines {
+        PromptLines::with_theme(Theme::shared_default())
+    }
+}
+
+impl PromptLines {
+    /// Creates an empty list of lines
+    pub fn new() -> PromptLines {
+        PromptLines::default()
+    }
+
+    /// Creates an empty list of lines drawn with `theme`
+    pub fn with_theme(theme: Arc<Theme>) -> PromptLines {
+        PromptLines {
+            lines: Vec::new(),
+            right: Vec::new(),
+            theme,
+            title: HashMap::new(),
+        }
+    }
+
+    /// Returns the theme the lines are drawn with
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Serializes the lines as JSON
+    pub fn to_json(&self) -> String {
+        json::render(self, false)
+    }
+
+    /// Serializes the lines as indented JSON
+    pub fn to_json_pretty(&self) -> String {
+        json::render(self, true)
+    }
+
+    /// Returns the style of `role` in the theme
+    pub fn style(&self, role: &str) -> Style {
+        self.theme.style(role)
+    }
+
+    /// Adds the boxes of a line to the end of the first line, next to the
+    /// working directory
+    pub fn push_first(&mut self, line: PromptLine) {
+        match self.lines.first_mut() {
+            Some(first) => first.parts.extend(line.parts),
+            None => self.lines.push(line),
+        }
+    }
+
+    /// Adds the boxes of a line to the right prompt
+    pub fn push_right(&mut self, line: PromptLine) {
+        self.right.extend(line.parts);
+    }
+
+    /// Returns the boxes that make up the right prompt
+    pub fn right(&self) -> &[PromptBox] {
+        &self.right
+    }
+
+    /// Sets a field that the terminal title template can show as `{name}`
+    pub fn set_title_field<T: fmt::Display>(&mut self, name: &str, value: T) {
+        self.title.insert(name.to_owned(), format!("{}", value));
+    }
+
+    pub(crate) fn title_fields(&self) -> &HashMap<String, String> {
+        &self.title
+    }
+
+    /// Marks where the lines end, so what is added next can be taken out
+    /// with `since`
+    pub(crate) fn mark(&self) -> LinesMark {
+        LinesMark {
+            lines: self.lines.len(),
+            first: self.lines.first().map_or(0, |l| l.parts.len()),
+            right: self.right.len(),
+            title: self.title.clone(),
+        }
+    }
+
+    /// Returns what was added after `mark`
+    pub(crate) fn since(&self, mark: &LinesMark) -> Addition {
+        let first = match self.lines.first() {
+            Some(first) if mark.lines > 0 => first.parts[mark.first..].to_vec(),
+            _ => Vec::new(),
+        };
+        let title = self
+            .title
+            .iter()
+            .filter(|&(k, v)| mark.title.get(k) != Some(v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Addition {
+            first,
+            lines: self.lines[mark.lines..].to_vec(),
+            right: self.right[mark.right..].to_vec(),
+            title,
+        }
+    }
+
+    /// Adds what was taken out of other lines with `since`
+    pub(crate) fn add(&mut self, addition: &Addition) {
+        if let Some(first) = self.lines.first_mut() {
+            first.parts.extend(addition.first.iter().cloned());
+        }
+        self.lines.extend(addition.lines.iter().cloned());
+        self.right.extend(addition.right.iter().cloned());
+        self.title.extend(addition.title.clone());
+    }
+
+    pub(crate) fn boxes_mut(&mut self) -> impl Iterator<Item = &mut PromptBox> {
+        self.lines
+            .iter_mut()
+            .flat_map(|l| l.parts.iter_mut())
+            .chain(self.right.iter_mut())
+    }
+}
+
+impl Deref for PromptLines {
+    type Target = Vec<PromptLine>;
+
+    fn deref(&self) -> &Vec<PromptLine> {
+        &self.lines
+    }
+}
+
+impl DerefMut for PromptLines {
+    fn deref_mut(&mut self) -> &mut Vec<PromptLine> {
+        &mut self.lines
+    }
+}
 
 /// `PromptLineBuilder`
 ///
@@ -136,7 +469,12 @@ impl PromptLineBuilder {
         self.indent_by(1)
     }
 
-    fn add_block<T: fmt::Display>(mut self, text: T, color: u32, bold: bool) -> PromptLineBuilder {
+    fn add_block<T: fmt::Display>(
+        mut self,
+        text: T,
+        color: Color,
+        bold: bool,
+    ) -> PromptLineBuilder {
         self.line
             .parts
             .push(PromptBox::new(format!("{}", text), color, bold, self.shell));
@@ -150,17 +488,120 @@ impl PromptLineBuilder {
     }
 
     /// Adds a block with a given text and color
-    pub fn colored_block<T: fmt::Display>(self, s: T, c: u32) -> PromptLineBuilder {
+    pub fn colored_block<T: fmt::Display>(self, s: T, c: Color) -> PromptLineBuilder {
         self.add_block(s, c, false)
     }
 
     /// Adds an emboldened block with a given text and color
-    pub fn bold_colored_block<T: fmt::Display>(self, s: T, c: u32) -> PromptLineBuilder {
+    pub fn bold_colored_block<T: fmt::Display>(self, s: T, c: Color) -> PromptLineBuilder {
         self.add_block(s, c, true)
     }
 
+    /// Adds a block drawn in a theme style
+    pub fn styled_block<T: fmt::Display>(mut self, s: T, style: Style) -> PromptLineBuilder {
+        self.line
+            .parts
+            .push(PromptBox::styled(format!("{}", s), style, self.shell));
+
+        self
+    }
+
+    /// Sets the background color of the most recently added block
+    pub fn with_background(mut self, c: Color) -> PromptLineBuilder {
+        if let Some(b) = self.line.parts.last_mut() {
+            b.set_background(c);
+        }
+
+        self
+    }
+
+    /// Adds a block of text the shell interprets, drawn in a theme style
+    ///
+    /// See `PromptBox::raw`
+    pub fn raw_block<T: fmt::Display>(mut self, s: T, style: Style) -> PromptLineBuilder {
+        self.line
+            .parts
+            .push(PromptBox::raw(format!("{}", s), style, self.shell));
+
+        self
+    }
+
+    /// Adds text in another style to the end of the most recently added
+    /// block, inside the same box
+    pub fn append_styled<T: fmt::Display>(mut self, s: T, style: Style) -> PromptLineBuilder {
+        if let Some(b) = self.line.parts.last_mut() {
+            b.append(format!("{}", s), style);
+        }
+
+        self
+    }
+
+    /// Sets what the shell expands the most recently added block to
+    pub fn expands_to<T: fmt::Display>(mut self, s: T) -> PromptLineBuilder {
+        if let Some(b) = self.line.parts.last_mut() {
+            b.set_expanded(format!("{}", s));
+        }
+
+        self
+    }
+
+    /// Makes the most recently added block a hyperlink to `url`
+    pub fn links_to<T: fmt::Display>(mut self, url: T) -> PromptLineBuilder {
+        if let Some(b) = self.line.parts.last_mut() {
+            b.set_link(format!("{}", url));
+        }
+
+        self
+    }
+
     /// Returns the built PromptLine
     pub fn build(self) -> PromptLine {
         self.line
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_escaped() {
+        let hostile = "$(reboot)`id`\\u%n!\x1B[2J";
+        let drawn = |shell: ShellType| {
+            PromptBox::styled(hostile.to_owned(), Style::default(), shell).linked_text(None)
+        };
+        assert_eq!(
+            drawn(ShellType::Bash),
+            "\\\\$(reboot)\\\\`id\\\\`\\\\\\\\u%n![2J"
+        );
+        assert_eq!(drawn(ShellType::Zsh), "$(reboot)`id`\\u%%n![2J");
+        assert_eq!(drawn(ShellType::Tcsh), "$(reboot)`id`\\\\u%%n\\![2J");
+        assert_eq!(drawn(ShellType::Fish), "$(reboot)`id`\\u%n![2J");
+
+        let raw = PromptBox::raw("\\w".to_owned(), Style::default(), ShellType::Bash);
+        assert_eq!(raw.linked_text(None), "\\w");
+    }
+
+    #[test]
+    fn tails_are_styled_like_boxes() {
+        let purple = Style {
+            fg: "#6c71c4".parse().ok(),
+            ..Style::default()
+        };
+        let mut line = ShellType::Fish
+            .new_line()
+            .styled_block("main", Style::default())
+            .append_styled(" -> ", Style::default())
+            .append_styled("origin/main", purple)
+            .build();
+        let b = &mut line.parts[0];
+        assert_eq!(b.plain_text(), "main -> origin/main");
+        assert_eq!(b.width(), 19);
+
+        b.downgrade(ColorSupport::Ansi16);
+        assert_eq!(b.linked_text(None), "main -> \x1B[94morigin/main");
+
+        b.truncate(10, "…");
+        assert_eq!(b.plain_text(), "main -> o…");
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The characters used to draw the lines of the prompt tree
+
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+pub const TOP: i16 = 8;
+pub const BOTTOM: i16 = 4;
+pub const LEFT: i16 = 2;
+pub const RIGHT: i16 = 1;
+
+// Indexed by `TOP | BOTTOM | LEFT | RIGHT` flags
+const LIGHT: [char; 16] = [
+    ' ', '╶', '╴', '─', '╷', '┌', '┐', '┬', '╵', '└', '┘', '┴', '│', '├', '┤', '┼',
+];
+const HEAVY: [char; 16] = [
+    ' ', '╺', '╸', '━', '╻', '┏', '┓', '┳', '╹', '┗', '┛', '┻', '┃', '┣', '┫', '╋',
+];
+// There are no double half lines, so those use the full ones
+const DOUBLE: [char; 16] = [
+    ' ', '═', '═', '═', '║', '╔', '╗', '╦', '║', '╚', '╝', '╩', '║', '╠', '╣', '╬',
+];
+const ROUNDED: [char; 16] = [
+    ' ', '╶', '╴', '─', '╷', '╭', '╮', '┬', '╵', '╰', '╯', '┴', '│', '├', '┤', '┼',
+];
+const ASCII: [char; 16] = [
+    ' ', '-', '-', '-', '|', '+', '+', '+', '|', '+', '+', '+', '|', '+', '+', '+',
+];
+
+/// The set of characters the prompt tree is drawn with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LineStyle {
+    /// `┌─┤`
+    #[default]
+    Light,
+
+    /// `┏━┫`
+    Heavy,
+
+    /// `╔═╣`
+    Double,
+
+    /// `╭─┤`
+    Rounded,
+
+    /// `+-|`, for terminals that can't show unicode
+    Ascii,
+}
+
+impl LineStyle {
+    /// Uses `Light` if the locale is UTF-8, and `Ascii` otherwise
+    pub fn from_env() -> LineStyle {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|v| env::var(v).ok())
+            .find(|v| !v.is_empty());
+        LineStyle::from_locale(locale.as_ref().map(|s| s.as_ref()))
+    }
+
+    /// Picks a style for a locale such as `en_US.UTF-8`
+    pub fn from_locale(locale: Option<&str>) -> LineStyle {
+        match locale.map(|l| l.to_lowercase()) {
+            Some(ref l) if l.contains("utf-8") || l.contains("utf8") => LineStyle::Light,
+            _ => LineStyle::Ascii,
+        }
+    }
+
+    /// Returns the character that connects the given sides of a cell
+    pub(crate) fn get(self, flags: i16) -> char {
+        let table = match self {
+            LineStyle::Light => &LIGHT,
+            LineStyle::Heavy => &HEAVY,
+            LineStyle::Double => &DOUBLE,
+            LineStyle::Rounded => &ROUNDED,
+            LineStyle::Ascii => &ASCII,
+        };
+        table[(flags & (TOP | BOTTOM | LEFT | RIGHT)) as usize]
+    }
+}
+
+impl FromStr for LineStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LineStyle, String> {
+        match s {
+            "light" => Ok(LineStyle::Light),
+            "heavy" => Ok(LineStyle::Heavy),
+            "double" => Ok(LineStyle::Double),
+            "rounded" => Ok(LineStyle::Rounded),
+            "ascii" => Ok(LineStyle::Ascii),
+            _ => Err(format!("unknown line style `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for LineStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            LineStyle::Light => "light",
+            LineStyle::Heavy => "heavy",
+            LineStyle::Double => "double",
+            LineStyle::Rounded => "rounded",
+            LineStyle::Ascii => "ascii",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_every_combination() {
+        for style in &[
+            LineStyle::Light,
+            LineStyle::Heavy,
+            LineStyle::Double,
+            LineStyle::Rounded,
+            LineStyle::Ascii,
+        ] {
+            for flags in 0..16 {
+                let c = style.get(flags);
+                assert_eq!(c == ' ', flags == 0, "{} {}", style, flags);
+            }
+        }
+
+        assert_eq!(LineStyle::Light.get(TOP | RIGHT), '└');
+        assert_eq!(LineStyle::Rounded.get(TOP | RIGHT), '╰');
+        assert_eq!(LineStyle::Ascii.get(TOP | BOTTOM), '|');
+    }
+
+    #[test]
+    fn detects_locale() {
+        assert_eq!(
+            LineStyle::from_locale(Some("en_US.UTF-8")),
+            LineStyle::Light
+        );
+        assert_eq!(LineStyle::from_locale(Some("de_DE.utf8")), LineStyle::Light);
+        assert_eq!(LineStyle::from_locale(Some("C")), LineStyle::Ascii);
+        assert_eq!(LineStyle::from_locale(None), LineStyle::Ascii);
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Link targets for OSC 8 hyperlinks
+//!
+//! Terminals only accept printable ASCII in link targets, and the shells
+//! interpret quotes, `$`, `\` and `!` in their prompts, so everything else is
+//! percent-encoded.
+
+use shell;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+fn percent_encode(bytes: &[u8], keep: &dyn Fn(u8) -> bool) -> String {
+    let mut retval = String::new();
+    for &b in bytes {
+        if keep(b) {
+            retval.push(char::from(b));
+        } else {
+            retval.push_str(&format!("%{:02X}", b));
+        }
+    }
+    retval
+}
+
+fn unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~".contains(&b)
+}
+
+/// Percent-encodes the characters of `url` that can't appear in a prompt
+///
+/// Existing percent-encoding is kept
+pub(crate) fn encode(url: &str) -> String {
+    percent_encode(url.as_bytes(), &|b| {
+        unreserved(b) || b":/?#[]@&()*+,;=%".contains(&b)
+    })
+}
+
+/// Returns the `file://` URL of `path` on this host
+pub fn file_url(path: &Path) -> String {
+    format!(
+        "file://{}{}",
+        shell::hostname(),
+        percent_encode(path.as_os_str().as_bytes(), &|b| unreserved(b) || b == b'/')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shell::ShellType;
+
+    #[test]
+    fn encodes_unsafe_characters() {
+        assert_eq!(
+            encode("https://example.com/a b/$(rm -rf ~)'\\!"),
+            "https://example.com/a%20b/%24(rm%20-rf%20~)%27%5C%21"
+        );
+        assert_eq!(
+            encode("https://example.com/%41?x=1#y"),
+            "https://example.com/%41?x=1#y"
+        );
+        assert!(file_url(Path::new("/tmp/a b#c%.txt")).ends_with("/tmp/a%20b%23c%25.txt"));
+    }
+
+    #[test]
+    fn shells_hide_links() {
+        assert_eq!(
+            ShellType::Bash.link_start("https://x/a b"),
+            "\\[\x1B]8;;https://x/a%20b\x07\\]"
+        );
+        assert_eq!(
+            ShellType::Zsh.link_start("https://x/%41"),
+            "%{\x1B]8;;https://x/%%41\x07%}"
+        );
+        assert_eq!(ShellType::Fish.link_end(), "\x1B]8;;\x07");
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The working directory segment
+//!
+//! Inside a git repository the path starts at the repository, named after
+//! its directory. Elsewhere the home directory is shown as `~`. Directories
+//! in the middle are shortened to their first letter when the path gets too
+//! long: `~/s/m/prompt_buffer/src`.
+
+use libc;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use width;
+
+/// The working directory, split up for display
+#[derive(Debug, PartialEq)]
+pub(crate) struct DirSegment {
+    /// `~`, `/`, or the name of the repository
+    pub root: String,
+
+    /// Whether `root` is the name of a git repository
+    pub in_repo: bool,
+
+    /// The directories below `root`
+    pub parts: Vec<String>,
+}
+
+/// Returns the closest directory above `path` that holds a `.git`
+fn repo_root(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| p.join(".git").exists())
+}
+
+/// Returns whether the user can create files in `path`
+pub(crate) fn is_writable(path: &Path) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(p) => unsafe { libc::access(p.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => true,
+    }
+}
+
+fn abbreviate(part: &str) -> String {
+    let skip = if part.starts_with('.') { 2 } else { 1 };
+    part.chars().take(skip).collect()
+}
+
+impl DirSegment {
+    /// Splits up `path`, looking for the repository it is in
+    pub fn new(path: &Path, home: Option<&Path>) -> DirSegment {
+        DirSegment::with_repo(path, home, repo_root(path))
+    }
+
+    fn with_repo(path: &Path, home: Option<&Path>, repo: Option<&Path>) -> DirSegment {
+        let parts_below = |base: &Path| {
+            path.strip_prefix(base).ok().map(|rest| {
+                rest.iter()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        // A repository in the home directory usually holds dotfiles, and
+        // says nothing about the directories below it
+        let repo = repo.filter(|r| Some(*r) != home && r.parent().is_some());
+        if let Some(repo) = repo {
+            if let Some(parts) = parts_below(repo) {
+                return DirSegment {
+                    root: repo
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    in_repo: true,
+                    parts,
+                };
+            }
+        }
+
+        if let Some(parts) = home.and_then(parts_below) {
+            return DirSegment {
+                root: "~".to_owned(),
+                in_repo: false,
+                parts,
+            };
+        }
+
+        DirSegment {
+            root: "/".to_owned(),
+            in_repo: false,
+            parts: parts_below(Path::new("/"))
+                .unwrap_or_else(|| vec![path.to_string_lossy().into_owned()]),
+        }
+    }
+
+    /// Returns the directories below the root joined by `/`, starting with
+    /// a `/` if there are any
+    pub fn rest(&self) -> String {
+        match (self.root.as_ref(), self.parts.is_empty()) {
+            (_, true) => String::new(),
+            ("/", false) => self.parts.join("/"),
+            _ => format!("/{}", self.parts.join("/")),
+        }
+    }
+
+    /// Returns the whole path as it is shown
+    pub fn plain(&self) -> String {
+        format!("{}{}", self.root, self.rest())
+    }
+
+    /// Shortens the directories between the root and the last one, starting
+    /// at the top, until the path fits in `columns`
+    pub fn shorten(&mut self, columns: usize) {
+        let last = self.parts.len().saturating_sub(1);
+        for ix in 0..last {
+            if width::display_width(&self.plain()) <= columns {
+                break;
+            }
+            self.parts[ix] = abbreviate(&self.parts[ix]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(path: &str, repo: Option<&str>) -> DirSegment {
+        DirSegment::with_repo(
+            Path::new(path),
+            Some(Path::new("/home/me")),
+            repo.map(Path::new),
+        )
+    }
+
+    #[test]
+    fn starts_at_home_or_repo() {
+        assert_eq!(segment("/home/me", None).plain(), "~");
+        assert_eq!(segment("/home/me/src", None).plain(), "~/src");
+        assert_eq!(segment("/usr/lib", None).plain(), "/usr/lib");
+        assert_eq!(segment("/", None).plain(), "/");
+        assert_eq!(segment("/home/meow", None).plain(), "/home/meow");
+
+        let repo = segment("/home/me/src/mega/prompt/src", Some("/home/me/src/mega"));
+        assert!(repo.in_repo);
+        assert_eq!(repo.root, "mega");
+        assert_eq!(repo.plain(), "mega/prompt/src");
+
+        let dotfiles = segment("/home/me/.config", Some("/home/me"));
+        assert!(!dotfiles.in_repo);
+        assert_eq!(dotfiles.plain(), "~/.config");
+    }
+
+    #[test]
+    fn shortens_middle_directories() {
+        let mut long = segment("/home/me/.config/some/deeply/nested/dir", None);
+        long.shorten(20);
+        assert_eq!(long.plain(), "~/.c/s/d/nested/dir");
+
+        long.shorten(0);
+        assert_eq!(long.plain(), "~/.c/s/d/n/dir");
+
+        let mut short = segment("/home/me/src/megaprompt", None);
+        short.shorten(20);
+        assert_eq!(short.plain(), "~/src/megaprompt");
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Draws `PromptLines` as powerline segments instead of a box-drawing tree.
+//!
+//! Boxed lines become a row of colored segments joined by arrows, free lines
+//! are printed as plain text. Indentation is kept so nested lines still read
+//! as a tree.
+
+use color::{self, Color};
+use line::{PromptBox, PromptLineType, PromptLines};
+use shell::ShellType;
+
+/// Used for boxes that don't set a background of their own, if the theme
+/// doesn't have one for `powerline`
+const DEFAULT_BACKGROUND: Color = color::BRIGHT_BLACK;
+
+fn background(lines: &PromptLines, b: &PromptBox) -> Color {
+    b.background()
+        .or(lines.style("powerline").bg)
+        .unwrap_or(DEFAULT_BACKGROUND)
+}
+
+fn segments(shell: ShellType, lines: &PromptLines, parts: &[PromptBox]) -> String {
+    let arrow = &lines.theme().glyphs().separator;
+    let mut retval = String::new();
+    let mut previous: Option<Color> = None;
+
+    for b in parts {
+        let bg = background(lines, b);
+        if let Some(prev) = previous {
+            retval = format!(
+                "{}{}{}",
+                retval,
+                shell.style(Some(prev), Some(bg), false),
+                arrow
+            );
+        }
+        retval = format!("{}{}", retval, b.segment(bg));
+        previous = Some(bg);
+    }
+
+    if let Some(prev) = previous {
+        retval = format!(
+            "{}{}{}{}{}",
+            retval,
+            shell.reset(),
+            shell.col(prev),
+            arrow,
+            shell.reset()
+        );
+    }
+
+    retval
+}
+
+/// Draws the left prompt, finishing with `dollar`
+pub(crate) fn left(shell: ShellType, lines: &PromptLines, dollar: &PromptBox) -> String {
+    let mut retval = String::new();
+
+    for line in lines.iter() {
+        let indent = "  ".repeat(usize::from(line.level));
+        let text = match line.line_type {
+            PromptLineType::Boxed => segments(shell, lines, &line.parts),
+            PromptLineType::Free => line
+                .parts
+                .iter()
+                .map(|b| format!(" {}", b))
+                .collect::<String>(),
+        };
+
+        retval = format!("{}{}{}{}", retval, indent, text, shell.newline());
+    }
+
+    format!("{}{} ", retval, dollar)
+}
+
+/// Draws the right prompt with arrows pointing left
+pub(crate) fn right(shell: ShellType, lines: &PromptLines) -> String {
+    let arrow = &lines.theme().glyphs().right_separator;
+    let mut retval = String::new();
+    let mut previous: Option<Color> = None;
+
+    for b in lines.right() {
+        let bg = background(lines, b);
+        retval = format!(
+            "{}{}{}{}",
+            retval,
+            shell.style(Some(bg), previous, false),
+            arrow,
+            b.segment(bg)
+        );
+        previous = Some(bg);
+    }
+
+    if previous.is_some() {
+        retval = format!("{}{}", retval, shell.reset());
+    }
+
+    retval
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_segments_with_arrows() {
+        let shell = ShellType::Fish;
+        let mut lines = PromptLines::new();
+        lines.push(
+            shell
+                .new_line()
+                .colored_block("a", color::WHITE)
+                .with_background(color::BLUE)
+                .block("b")
+                .build(),
+        );
+        let dollar = PromptBox::new("$".to_owned(), color::RED, false, shell);
+
+        assert_eq!(
+            left(shell, &lines, &dollar),
+            "\x1B[37;44m a \x1B[34;100m\u{E0B0}\x1B[35;100m b \x1B[0m\x1B[90m\u{E0B0}\x1B[0m\n\
+             \x1B[31m$\x1B[0m "
+        );
+    }
+}
@@ -8,8 +8,14 @@
 
 //! Code to handle outputting strungs to the shell.
 
-use std::fmt;
+use color::Color;
+use libc;
 use line::PromptLineBuilder;
+use link;
+use std::ffi::CStr;
+use std::fmt;
+use std::str::FromStr;
+use theme::Style;
 
 /// Defines the shell type to output for
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
@@ -19,6 +25,18 @@ pub enum ShellType {
 
     /// Z Shell
     Zsh,
+
+    /// Friendly interactive shell
+    Fish,
+
+    /// Nushell
+    Nushell,
+
+    /// TENEX C shell (also used for csh)
+    Tcsh,
+
+    /// Korn shell (mksh conventions)
+    Ksh,
 }
 
 impl ShellType {
@@ -32,45 +50,148 @@ impl ShellType {
         PromptLineBuilder::new_free(*self)
     }
 
-    /// Returns the escape for showing the working directory
-    pub fn dir(&self) -> &'static str {
+    /// Returns the escape for showing the current hostname
+    pub fn hostname(&self) -> String {
         match *self {
-            ShellType::Bash => r#"\w"#,
-            ShellType::Zsh => "%~",
+            ShellType::Bash => r#"\H"#.to_owned(),
+            ShellType::Zsh => "%m".to_owned(),
+            ShellType::Tcsh => "%M".to_owned(),
+            ShellType::Fish | ShellType::Nushell | ShellType::Ksh => hostname(),
         }
     }
 
-    /// Returns the escape for showing the current hostname
-    pub fn hostname(&self) -> &'static str {
+    /// Returns what the shell shows for `hostname`
+    pub fn expanded_hostname(&self) -> String {
+        let host = hostname();
         match *self {
-            ShellType::Bash => r#"\H"#,
-            ShellType::Zsh => "%m",
+            ShellType::Zsh => host.split('.').next().unwrap_or("").to_owned(),
+            _ => host,
         }
     }
 
     /// Returns the escape for showing the current root/not root state of shell
-    pub fn dollar(&self) -> &'static str {
+    pub fn dollar(&self) -> String {
         match *self {
-            ShellType::Bash => r#"\$"#,
-            ShellType::Zsh => "%#",
+            ShellType::Bash => r#"\$"#.to_owned(),
+            ShellType::Zsh | ShellType::Tcsh => "%#".to_owned(),
+            ShellType::Fish | ShellType::Nushell | ShellType::Ksh => {
+                if is_root() { "#" } else { "$" }.to_owned()
+            }
         }
     }
 
-    fn col_cmd<T: fmt::Display>(&self, c: &T) -> String {
+    /// Returns the text that has to open the prompt
+    ///
+    /// mksh only honours non-printing delimiters when the prompt starts
+    /// with the delimiter followed by a carriage return
+    pub fn prompt_start(&self) -> &'static str {
+        match *self {
+            ShellType::Ksh => "\x01\r",
+            _ => "",
+        }
+    }
+
+    /// Returns the separator between prompt lines
+    ///
+    /// tcsh splits command substitutions on newlines, so it gets the `\n`
+    /// escape that it expands when printing the prompt
+    pub fn newline(&self) -> &'static str {
+        match *self {
+            ShellType::Tcsh => r#"\n"#,
+            _ => "\n",
+        }
+    }
+
+    /// Wraps text that the terminal acts on but doesn't show, so the shell
+    /// leaves it out when measuring the prompt
+    fn hidden(&self, s: &str) -> String {
         match *self {
-            ShellType::Bash => format!(r#"\[{}[{}\]"#, '\x1B', c),
-            ShellType::Zsh => format!(r#"%{{{}[{}%}}"#, '\x1B', c),
+            ShellType::Bash => format!(r#"\[{}\]"#, s),
+            ShellType::Zsh | ShellType::Tcsh => format!(r#"%{{{}%}}"#, s),
+            ShellType::Fish | ShellType::Nushell => s.to_owned(),
+            ShellType::Ksh => format!("\x01{}\x01", s),
+        }
+    }
+
+    fn col_cmd<T: fmt::Display>(&self, c: &T) -> String {
+        self.hidden(&format!("{}[{}", '\x1B', c))
+    }
+
+    /// Returns the escape sequence that starts a hyperlink to `url`
+    ///
+    /// The sequence ends with BEL rather than `ESC \`, which bash would
+    /// read as the end of its non-printing marker
+    pub fn link_start(&self, url: &str) -> String {
+        let url = link::encode(url);
+        let url = match *self {
+            ShellType::Zsh | ShellType::Tcsh => url.replace('%', "%%"),
+            _ => url,
+        };
+        self.hidden(&format!("\x1B]8;;{}\x07", url))
+    }
+
+    /// Returns the escape sequence that ends a hyperlink
+    pub fn link_end(&self) -> String {
+        self.hidden("\x1B]8;;\x07")
+    }
+
+    /// Escapes `s` so the shell shows it as is in its prompt
+    ///
+    /// Control characters are dropped, so text can't smuggle in escape
+    /// sequences of its own. zsh only gets `%` escaped: the init script
+    /// keeps the prompt in a parameter, which prompt_subst doesn't expand
+    /// again
+    pub fn escape(&self, s: &str) -> String {
+        let mut retval = String::new();
+        for c in s.chars().filter(|c| !c.is_control()) {
+            match (*self, c) {
+                (ShellType::Bash, '\\') => retval.push_str(r#"\\\\"#),
+                (ShellType::Bash, '$') => retval.push_str(r#"\\$"#),
+                (ShellType::Bash, '`') => retval.push_str(r#"\\`"#),
+                (ShellType::Zsh, '%') | (ShellType::Tcsh, '%') => retval.push_str("%%"),
+                (ShellType::Tcsh, '!') => retval.push_str(r#"\!"#),
+                (ShellType::Tcsh, '\\') => retval.push_str(r#"\\"#),
+                (ShellType::Ksh, '!') => retval.push_str("!!"),
+                _ => retval.push(c),
+            }
+        }
+        retval
+    }
+
+    /// Returns the escape sequence that sets the terminal title to `title`
+    pub fn title(&self, title: &str) -> String {
+        self.hidden(&format!("\x1B]0;{}\x07", self.escape(title)))
+    }
+
+    /// Returns a single escape sequence that sets all of the given attributes
+    pub fn style(&self, fg: Option<Color>, bg: Option<Color>, bold: bool) -> String {
+        let mut params = Vec::new();
+        if bold {
+            params.push("1".to_owned());
+        }
+        if let Some(c) = fg {
+            params.push(c.sgr(false));
         }
+        if let Some(c) = bg {
+            params.push(c.sgr(true));
+        }
+
+        self.col_cmd(&format!("{}m", params.join(";")))
     }
 
     /// Returns a foreground color escape sequence
-    pub fn col(&self, c: u32) -> String {
-        self.col_cmd(&format!("{}m", c + 30))
+    pub fn col(&self, c: Color) -> String {
+        self.style(Some(c), None, false)
+    }
+
+    /// Returns the escape sequence for a theme style
+    pub fn paint(&self, style: Style) -> String {
+        self.style(style.fg, style.bg, style.bold)
     }
 
     /// Returns a bold foreground color escape sequence
-    pub fn bcol(&self, c: u32) -> String {
-        self.col_cmd(&format!("1;{}m", c + 30))
+    pub fn bcol(&self, c: Color) -> String {
+        self.style(Some(c), None, true)
     }
 
     /// Returns a reset sequence
@@ -78,3 +199,38 @@ impl ShellType {
         self.col_cmd(&"0m".to_owned())
     }
 }
+
+impl FromStr for ShellType {
+    type Err = String;
+
+    /// Parses a shell name, ignoring case
+    fn from_str(s: &str) -> Result<ShellType, String> {
+        match s.to_lowercase().as_ref() {
+            "bash" => Ok(ShellType::Bash),
+            "zsh" => Ok(ShellType::Zsh),
+            "fish" => Ok(ShellType::Fish),
+            "nushell" | "nu" => Ok(ShellType::Nushell),
+            "tcsh" | "csh" => Ok(ShellType::Tcsh),
+            "ksh" | "mksh" => Ok(ShellType::Ksh),
+            _ => Err(format!("unknown shell `{}`", s)),
+        }
+    }
+}
+
+pub(crate) fn hostname() -> String {
+    let mut buf = [0 as libc::c_char; 256];
+    let ok = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } == 0;
+    if !ok {
+        return "localhost".to_owned();
+    }
+
+    // gethostname does not guarantee termination when the name is truncated
+    buf[buf.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
@@ -0,0 +1,94 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! What the shell knows about the command that just finished
+
+use libc;
+use std::time::Duration;
+
+const SIGNALS: [(libc::c_int, &str); 27] = [
+    (libc::SIGHUP, "SIGHUP"),
+    (libc::SIGINT, "SIGINT"),
+    (libc::SIGQUIT, "SIGQUIT"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGTRAP, "SIGTRAP"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGKILL, "SIGKILL"),
+    (libc::SIGUSR1, "SIGUSR1"),
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGUSR2, "SIGUSR2"),
+    (libc::SIGPIPE, "SIGPIPE"),
+    (libc::SIGALRM, "SIGALRM"),
+    (libc::SIGTERM, "SIGTERM"),
+    (libc::SIGCHLD, "SIGCHLD"),
+    (libc::SIGCONT, "SIGCONT"),
+    (libc::SIGSTOP, "SIGSTOP"),
+    (libc::SIGTSTP, "SIGTSTP"),
+    (libc::SIGTTIN, "SIGTTIN"),
+    (libc::SIGTTOU, "SIGTTOU"),
+    (libc::SIGURG, "SIGURG"),
+    (libc::SIGXCPU, "SIGXCPU"),
+    (libc::SIGXFSZ, "SIGXFSZ"),
+    (libc::SIGVTALRM, "SIGVTALRM"),
+    (libc::SIGPROF, "SIGPROF"),
+    (libc::SIGWINCH, "SIGWINCH"),
+];
+
+/// State of the shell the prompt is drawn for
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShellState {
+    /// Exit status of the last command, if the shell passed it along
+    pub exit_status: Option<i32>,
+
+    /// How long the last command ran for, if the shell timed it
+    pub duration: Option<Duration>,
+
+    /// Number of background and suspended jobs, if the shell counted them
+    pub jobs: Option<u32>,
+}
+
+impl ShellState {
+    /// Returns whether the last command is known to have failed
+    pub fn failed(&self) -> bool {
+        self.exit_status.map(|s| s != 0).unwrap_or(false)
+    }
+
+    /// Returns the name of the signal that killed the last command
+    ///
+    /// Shells report a command killed by signal `n` as exiting with `128 + n`
+    pub fn signal_name(&self) -> Option<&'static str> {
+        let signal = self.exit_status?.checked_sub(128)?;
+        SIGNALS
+            .iter()
+            .find(|&&(n, _)| n == signal)
+            .map(|&(_, name)| name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShellState;
+
+    #[test]
+    fn names_signals() {
+        let state = |s| ShellState {
+            exit_status: Some(s),
+            ..ShellState::default()
+        };
+        assert_eq!(state(130).signal_name(), Some("SIGINT"));
+        assert_eq!(state(137).signal_name(), Some("SIGKILL"));
+        assert_eq!(state(1).signal_name(), None);
+        assert_eq!(state(200).signal_name(), None);
+        assert_eq!(state(i32::MIN).signal_name(), None);
+        assert!(state(1).failed());
+        assert!(!state(0).failed());
+        assert!(!ShellState::default().failed());
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Themes map the roles of prompt text (`git.header`, `prompt.char`, ...)
+//! to styles, and pick the glyphs used to draw the prompt
+//!
+//! A theme is a TOML file:
+//!
+//! ```toml
+//! [glyphs]
+//! ellipsis = "..."
+//!
+//! [styles]
+//! "git.header" = "cyan"
+//! "git.status.partial" = { fg = "#ff8700", bold = true }
+//! "powerline" = { bg = "bright-black" }
+//! ```
+//!
+//! Every theme is layered on top of the default one. A role that isn't
+//! listed falls back to its parent, so `git.status.modified` uses the style
+//! of `git.status` when it has none of its own.
+
+use color::Color;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::{Arc, OnceLock};
+use toml;
+
+const BUILTIN: [(&str, &str); 3] = [
+    ("default", include_str!("themes/default.toml")),
+    ("solarized", include_str!("themes/solarized.toml")),
+    ("mono", include_str!("themes/mono.toml")),
+];
+
+/// How a piece of text is colored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    /// Text color, the terminal's default if not set
+    pub fg: Option<Color>,
+
+    /// Background color, the terminal's default if not set
+    pub bg: Option<Color>,
+
+    /// Whether the text is bold
+    pub bold: bool,
+}
+
+impl Style {
+    /// Returns the same style in bold
+    pub fn bold(self) -> Style {
+        Style { bold: true, ..self }
+    }
+}
+
+/// Characters the renderers draw with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glyphs {
+    /// Joins powerline segments on the left prompt
+    pub separator: String,
+
+    /// Joins powerline segments on the right prompt
+    pub right_separator: String,
+
+    /// Ends boxes that were shortened to fit the terminal
+    pub ellipsis: String,
+
+    /// Marks directories the user can't write to
+    pub readonly: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct GlyphsFile {
+    separator: Option<String>,
+    right_separator: Option<String>,
+    ellipsis: Option<String>,
+    readonly: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleTable {
+    fg: Option<String>,
+    bg: Option<String>,
+    #[serde(default)]
+    bold: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StyleFile {
+    Fg(String),
+    Full(StyleTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    #[serde(default)]
+    glyphs: GlyphsFile,
+    #[serde(default)]
+    styles: HashMap<String, StyleFile>,
+}
+
+/// A set of styles and glyphs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    styles: HashMap<String, Style>,
+    glyphs: Glyphs,
+}
+
+fn parse_color(role: &str, c: Option<String>) -> Result<Option<Color>, String> {
+    c.map(|c| c.parse().map_err(|e| format!("{}: {}", role, e)))
+        .transpose()
+}
+
+impl Theme {
+    fn empty() -> Theme {
+        Theme {
+            styles: HashMap::new(),
+            glyphs: Glyphs {
+                separator: String::new(),
+                right_separator: String::new(),
+                ellipsis: String::new(),
+                readonly: String::new(),
+            },
+        }
+    }
+
+    /// Returns the built-in default theme
+    pub fn shared_default() -> Arc<Theme> {
+        static DEFAULT: OnceLock<Arc<Theme>> = OnceLock::new();
+        DEFAULT
+            .get_or_init(|| {
+                Arc::new(
+                    Theme::empty()
+                        .layer(BUILTIN[0].1)
+                        .expect("the default theme is invalid"),
+                )
+            })
+            .clone()
+    }
+
+    /// Returns the names of the built-in themes
+    pub fn builtin_names() -> Vec<&'static str> {
+        BUILTIN.iter().map(|&(name, _)| name).collect()
+    }
+
+    /// Parses a theme file, on top of the default theme
+    pub fn parse(s: &str) -> Result<Theme, String> {
+        (*Theme::shared_default()).clone().layer(s)
+    }
+
+    /// Loads a built-in theme by name, or a theme file by path
+    pub fn load(spec: &str) -> Result<Theme, String> {
+        match BUILTIN.iter().find(|&&(name, _)| name == spec) {
+            Some(&(_, s)) => Theme::parse(s),
+            None => {
+                let s = fs::read_to_string(spec)
+                    .map_err(|e| format!("unable to read theme `{}`: {}", spec, e))?;
+                Theme::parse(&s).map_err(|e| format!("{}: {}", spec, e))
+            }
+        }
+    }
+
+    /// Returns the theme named by `$MEGAPROMPT_THEME`, if it is set
+    pub fn spec_from_env() -> Option<String> {
+        env::var("MEGAPROMPT_THEME").ok().filter(|s| !s.is_empty())
+    }
+
+    fn layer(mut self, s: &str) -> Result<Theme, String> {
+        let file: ThemeFile = toml::from_str(s).map_err(|e| format!("{}", e).trim().to_owned())?;
+
+        for (role, style) in file.styles {
+            let style = match style {
+                StyleFile::Fg(fg) => Style {
+                    fg: parse_color(&role, Some(fg))?,
+                    ..Style::default()
+                },
+                StyleFile::Full(t) => Style {
+                    fg: parse_color(&role, t.fg)?,
+                    bg: parse_color(&role, t.bg)?,
+                    bold: t.bold,
+                },
+            };
+            self.styles.insert(role, style);
+        }
+
+        let glyphs = file.glyphs;
+        self.glyphs = Glyphs {
+            separator: glyphs.separator.unwrap_or(self.glyphs.separator),
+            right_separator: glyphs
+                .right_separator
+                .unwrap_or(self.glyphs.right_separator),
+            ellipsis: glyphs.ellipsis.unwrap_or(self.glyphs.ellipsis),
+            readonly: glyphs.readonly.unwrap_or(self.glyphs.readonly),
+        };
+
+        Ok(self)
+    }
+
+    /// Returns the style for `role`, or for its closest parent role
+    pub fn style(&self, role: &str) -> Style {
+        let mut role = role;
+        loop {
+            if let Some(style) = self.styles.get(role) {
+                return *style;
+            }
+            match role.rfind('.') {
+                Some(ix) => role = &role[..ix],
+                None => return Style::default(),
+            }
+        }
+    }
+
+    /// Returns the glyphs of the theme
+    pub fn glyphs(&self) -> &Glyphs {
+        &self.glyphs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color;
+
+    #[test]
+    fn builtin_themes_parse() {
+        for name in Theme::builtin_names() {
+            assert!(Theme::load(name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn layers_on_the_default() {
+        let theme = Theme::parse(
+            r##"
+            [glyphs]
+            ellipsis = "..."
+
+            [styles]
+            "git.header" = { fg = "#ff8700", bold = true }
+            "git.status" = "green"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(
+            theme.style("git.header"),
+            Style {
+                fg: Some(Color::Rgb(0xff, 0x87, 0x00)),
+                bg: None,
+                bold: true,
+            }
+        );
+        assert_eq!(theme.style("prompt.char").fg, Some(color::RED));
+        assert_eq!(theme.style("git.status.modified").fg, Some(color::BLUE));
+        assert_eq!(theme.style("git.status.other").fg, Some(color::GREEN));
+        assert_eq!(theme.style("nothing.here"), Style::default());
+        assert_eq!(theme.glyphs().ellipsis, "...");
+        assert_eq!(theme.glyphs().separator, "\u{E0B0}");
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            Theme::parse(r#"styles = { "git.header" = "mauve" }"#),
+            Err("git.header: invalid color `mauve`".to_owned())
+        );
+        assert_eq!(
+            Theme::parse(r#"styles = { "git.header" = { bg = "300" } }"#),
+            Err("git.header: invalid color `300`".to_owned())
+        );
+        assert!(Theme::parse("[styles]\n\"a\" = { colour = \"red\" }").is_err());
+        assert!(Theme::load("/does/not/exist.toml").is_err());
+    }
+}
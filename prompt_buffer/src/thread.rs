@@ -6,9 +6,10 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! Used to allow a thread per path. This way the cached value can be
-//! different based on which path it is running from. For paths with
-//! slow `prompt.to_string` outputs, this is particularily useful.
+//! Used to allow a thread per path, so that paths with slow plugins don't
+//! hold up prompts elsewhere. A prompt that takes too long is drawn again
+//! from what the plugins drew for the last answer, with only the plugins
+//! that show the state of the request it answers run again.
 //!
 //! Thred will run for 10 minutes after the last request, to avoid
 //! leaking too many threads.
@@ -17,16 +18,35 @@ use std::path::PathBuf;
 use std::thread;
 use std::time::Duration;
 
-use buffer::{PluginSpeed, PromptBuffer};
+use buffer::{PluginSpeed, PromptBuffer, PromptOutput, RenderOptions};
+use context::PromptContext;
 use error::PromptBufferResult;
+use line::Addition;
+
+/// What a prompt thread is asked to do
+enum Request {
+    /// Draw the prompt, answering with the number of the request
+    Render(u64, RenderOptions, PromptContext),
+
+    /// Draw future prompts with another buffer, such as after the plugin
+    /// config changed
+    Replace(PromptBuffer),
+}
 
 /// Stores information about prompt threads
 pub struct PromptThread {
-    send: Sender<()>,
-    recv: Receiver<String>,
+    send: Sender<Request>,
+    recv: Receiver<(u64, PromptOutput, Vec<Addition>)>,
     death: Receiver<()>,
     path: PathBuf,
-    cached: String,
+    options: RenderOptions,
+
+    /// Number of the last render request. Replies to earlier ones are
+    /// dropped, as they show the state of an earlier command
+    request: u64,
+
+    /// What the plugins drew for the last answer, whatever request it was for
+    last: Option<Vec<Addition>>,
     alive: bool,
 }
 
@@ -46,6 +66,7 @@ impl PromptThread {
     /// Creates a new prompt thread for a given path
     pub fn new(
         path: PathBuf,
+        options: RenderOptions,
         make_prompt: &dyn Fn() -> PromptBuffer,
     ) -> PromptBufferResult<PromptThread> {
         let (tx_notify, rx_notify) = chan::r#async();
@@ -54,12 +75,12 @@ impl PromptThread {
 
         let p = path.clone();
         let mut prompt = make_prompt();
-        let cached = prompt.convert_to_string_ext(PluginSpeed::Fast);
+        prompt.set_render_options(options.clone());
         let name = format!("{}", path.display());
         thread::Builder::new()
             .name(name.to_owned())
             .spawn(move || {
-                prompt.set_path(p);
+                prompt.set_path(p.clone());
 
                 loop {
                     let timeout = oneshot_timer(Duration::from_secs(10 * 60));
@@ -68,8 +89,23 @@ impl PromptThread {
                     #[allow(unused_mut)]
                     {
                         chan_select! {
-                            rx_notify.recv() => {
-                                tx_prompt.send(prompt.convert_to_string())
+                            rx_notify.recv() -> request => {
+                                match request {
+                                    Some(Request::Replace(new_prompt)) => {
+                                        prompt = new_prompt;
+                                        prompt.set_path(p.clone());
+                                    }
+                                    Some(Request::Render(id, options, context)) => {
+                                        prompt.set_render_options(options);
+                                        prompt.set_context(context);
+                                        let output = prompt.convert_to_output(PluginSpeed::Slow);
+                                        tx_prompt.send((id, output, prompt.drawn().to_vec()))
+                                    }
+                                    None => {
+                                        tx_death.send(());
+                                        break;
+                                    }
+                                }
                             },
                             timeout.recv() => {
                                 info!("Thread {} timed out", name);
@@ -86,7 +122,9 @@ impl PromptThread {
             recv: rx_prompt,
             death: rx_death,
             path,
-            cached,
+            options,
+            request: 0,
+            last: None,
             alive: true,
         })
     }
@@ -108,13 +146,30 @@ impl PromptThread {
     }
 
     fn revive(&mut self, make_prompt: &dyn Fn() -> PromptBuffer) -> PromptBufferResult<()> {
-        *self = PromptThread::new(self.path.clone(), make_prompt)?;
+        *self = PromptThread::new(self.path.clone(), self.options.clone(), make_prompt)?;
         Ok(())
     }
 
-    /// Gets a result out of the prompt thread, or return a cached result
-    /// if the response takes more than 100 milliseconds
-    pub fn get(&mut self, make_prompt: &dyn Fn() -> PromptBuffer) -> PromptBufferResult<String> {
+    /// Swaps the prompt the thread draws for a new one, keeping the thread
+    ///
+    /// `get` has to be given the same `make_prompt` from then on, as slow
+    /// answers are drawn with it
+    pub fn replace(&mut self, make_prompt: &dyn Fn() -> PromptBuffer) {
+        let mut prompt = make_prompt();
+        prompt.set_render_options(self.options.clone());
+        self.send.send(Request::Replace(prompt));
+        self.last = None;
+    }
+
+    /// Gets a result out of the prompt thread, or draws the prompt again
+    /// from the last answer if the response takes more than 50 milliseconds
+    pub fn get(
+        &mut self,
+        options: RenderOptions,
+        context: PromptContext,
+        make_prompt: &dyn Fn() -> PromptBuffer,
+    ) -> PromptBufferResult<PromptOutput> {
+        self.options = options.clone();
         info!("Checking lifesigns");
         if !self.check_is_alive() {
             info!("Thread is not alive. Reviving it");
@@ -122,7 +177,10 @@ impl PromptThread {
         }
 
         info!("Asking for a new prompt");
-        self.send.send(());
+        self.request += 1;
+        let id = self.request;
+        self.send
+            .send(Request::Render(id, options.clone(), context.clone()));
 
         info!("Creating timeout");
         let timeout = oneshot_timer(Duration::from_millis(50));
@@ -133,16 +191,30 @@ impl PromptThread {
             {
                 chan_select! {
                     default =>{},
-                    recv.recv() -> text => {
-                        info!("Got text");
-                        if let Some(t) = text {
-                            self.cached = t;
-                            return Ok(self.cached.clone());
+                    recv.recv() -> reply => {
+                        if let Some((reply_id, output, drawn)) = reply {
+                            self.last = Some(drawn);
+                            if reply_id == id {
+                                info!("Got text");
+                                return Ok(output);
+                            }
+                            info!("Keeping the answer to an earlier request to draw from");
                         }
                     },
                     timeout.recv() => {
-                        info!("Got timeout");
-                        return Ok(self.cached.clone());
+                        let mut prompt = make_prompt();
+                        prompt.set_render_options(options);
+                        prompt.set_context(context);
+                        return Ok(match self.last {
+                            Some(ref drawn) => {
+                                info!("Got timeout. Drawing from the last answer");
+                                prompt.redraw(drawn)
+                            }
+                            None => {
+                                info!("Got timeout. Drawing without slow plugins");
+                                prompt.convert_to_output(PluginSpeed::Fast)
+                            }
+                        });
                     }
                 }
             }
@@ -150,3 +222,142 @@ impl PromptThread {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffer::{Format, PromptBufferPlugin};
+    use line::PromptLines;
+    use shell::ShellType;
+
+    struct HelloPlugin;
+
+    impl PromptBufferPlugin for HelloPlugin {
+        fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+            lines.push(context.shell.new_line().block("hello").build());
+        }
+    }
+
+    #[test]
+    fn plugins_can_be_replaced() {
+        let options = RenderOptions {
+            format: Format::Json,
+            ..RenderOptions::default()
+        };
+        let context = PromptContext::new(ShellType::Fish, PathBuf::from("/"));
+        let plain = || PromptBuffer::new(ShellType::Fish);
+        let mut thread = PromptThread::new(PathBuf::from("/"), options.clone(), &plain).unwrap();
+
+        let hello = || {
+            let mut prompt = PromptBuffer::new(ShellType::Fish);
+            prompt.add_plugin(HelloPlugin);
+            prompt.add_plugin(SlowStatusPlugin);
+            prompt
+        };
+        thread.replace(&hello);
+
+        // The thread misses the timeout, so the answers are drawn without
+        // slow plugins, by the new ones
+        for _ in 0..2 {
+            let output = thread
+                .get(options.clone(), context.clone(), &hello)
+                .unwrap();
+            assert!(output.left.contains("hello"));
+        }
+    }
+
+    /// Shows the exit status, taking its time when it may
+    struct SlowStatusPlugin;
+
+    impl PromptBufferPlugin for SlowStatusPlugin {
+        fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+            if context.speed == PluginSpeed::Slow {
+                thread::sleep(Duration::from_millis(80));
+            }
+            let status = format!("status {:?}", context.state.exit_status);
+            lines.push(context.shell.new_line().block(status).build());
+        }
+
+        fn uses_state(&self) -> bool {
+            true
+        }
+    }
+
+    /// Only draws anything when it may take its time, like git status
+    struct SlowOnlyPlugin;
+
+    impl PromptBufferPlugin for SlowOnlyPlugin {
+        fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+            if context.speed == PluginSpeed::Slow {
+                thread::sleep(Duration::from_millis(80));
+                lines.push(context.shell.new_line().block("slow").build());
+            }
+        }
+    }
+
+    /// Shows the exit status right away
+    struct StatusPlugin;
+
+    impl PromptBufferPlugin for StatusPlugin {
+        fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+            let status = format!("status {:?}", context.state.exit_status);
+            lines.push(context.shell.new_line().block(status).build());
+        }
+
+        fn uses_state(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn timeouts_are_drawn_from_the_last_answer() {
+        let options = RenderOptions {
+            format: Format::Json,
+            ..RenderOptions::default()
+        };
+        let make_prompt = || {
+            let mut prompt = PromptBuffer::new(ShellType::Fish);
+            prompt.add_plugin(SlowOnlyPlugin);
+            prompt.add_plugin(StatusPlugin);
+            prompt
+        };
+        let mut thread =
+            PromptThread::new(PathBuf::from("/"), options.clone(), &make_prompt).unwrap();
+
+        let mut context = PromptContext::new(ShellType::Fish, PathBuf::from("/"));
+        context.state.exit_status = Some(1);
+        let first = thread
+            .get(options.clone(), context.clone(), &make_prompt)
+            .unwrap();
+        assert!(!first.left.contains("slow"));
+
+        // The answer to the first request comes in while the second waits,
+        // and the second is drawn from it with its own exit status
+        context.state.exit_status = Some(2);
+        let second = thread.get(options.clone(), context, &make_prompt).unwrap();
+        assert!(second.left.contains("slow"));
+        assert!(second.left.contains("status Some(2)"));
+    }
+
+    #[test]
+    fn answers_show_the_state_of_their_request() {
+        let options = RenderOptions {
+            format: Format::Json,
+            ..RenderOptions::default()
+        };
+        let make_prompt = || {
+            let mut prompt = PromptBuffer::new(ShellType::Fish);
+            prompt.add_plugin(SlowStatusPlugin);
+            prompt
+        };
+        let mut thread =
+            PromptThread::new(PathBuf::from("/"), options.clone(), &make_prompt).unwrap();
+
+        for status in 1..4 {
+            let mut context = PromptContext::new(ShellType::Fish, PathBuf::from("/"));
+            context.state.exit_status = Some(status);
+            let output = thread.get(options.clone(), context, &make_prompt).unwrap();
+            assert!(output.left.contains(&format!("status Some({})", status)));
+        }
+    }
+}
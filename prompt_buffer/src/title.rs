@@ -0,0 +1,94 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fills in the template of the terminal title
+//!
+//! `{name}` is replaced by the title field `name`. A part in square brackets
+//! is left out when any field in it is empty or missing, so
+//! `[{repo}:{branch} — ]{dir}` is just the directory outside of a repository.
+
+use std::collections::HashMap;
+use std::env;
+
+/// Returns the title template from `$MEGAPROMPT_TITLE`. The title is left
+/// alone unless it is set to something
+pub(crate) fn template_from_env() -> Option<String> {
+    env::var("MEGAPROMPT_TITLE").ok().filter(|t| !t.is_empty())
+}
+
+/// Fills in `{name}` fields, returning whether all of them had a value
+fn fill(template: &str, fields: &HashMap<String, String>) -> (String, bool) {
+    let mut retval = String::new();
+    let mut complete = true;
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        retval.push_str(&rest[..start]);
+        match rest[start..].find('}') {
+            Some(len) => {
+                let name = &rest[start + 1..start + len];
+                match fields.get(name).filter(|v| !v.is_empty()) {
+                    Some(value) => retval.push_str(value),
+                    None => complete = false,
+                }
+                rest = &rest[start + len + 1..];
+            }
+            None => {
+                retval.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    retval.push_str(rest);
+
+    (retval, complete)
+}
+
+pub(crate) fn render(template: &str, fields: &HashMap<String, String>) -> String {
+    let mut retval = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('[') {
+        retval.push_str(&fill(&rest[..start], fields).0);
+        let end = rest[start..]
+            .find(']')
+            .map(|e| start + e)
+            .unwrap_or(rest.len());
+        let (part, complete) = fill(&rest[start + 1..end], fields);
+        if complete {
+            retval.push_str(&part);
+        }
+        rest = if end < rest.len() {
+            &rest[end + 1..]
+        } else {
+            ""
+        };
+    }
+    retval.push_str(&fill(rest, fields).0);
+
+    retval
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_out_incomplete_parts() {
+        let template = "[{repo}:{branch} — ]{dir}";
+        let mut fields = HashMap::new();
+        fields.insert("dir".to_owned(), "~/src".to_owned());
+        assert_eq!(render(template, &fields), "~/src");
+
+        fields.insert("repo".to_owned(), "megaprompt".to_owned());
+        fields.insert("branch".to_owned(), "main".to_owned());
+        assert_eq!(render(template, &fields), "megaprompt:main — ~/src");
+        assert_eq!(render("{user}@{dir} [{nope}]", &fields), "@~/src ");
+        assert_eq!(render("[unclosed {dir}", &fields), "unclosed ~/src");
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Measures how many terminal columns prompt text takes up
+//!
+//! Escape sequences and the shells' non-printing markers (`\[ \]`, `%{ %}`
+//! and `\x01`) take up no space, wide characters take up two columns.
+
+use libc;
+use std::env;
+use std::mem;
+use unicode_width::UnicodeWidthChar;
+
+/// Splits `s` into pieces, and whether each piece is shown on screen
+fn pieces(s: &str) -> Vec<(&str, bool)> {
+    let mut retval = Vec::new();
+    let mut rest = s;
+
+    while let Some(c) = rest.chars().next() {
+        let hidden = if rest.starts_with("\\[") {
+            rest.find("\\]").map(|i| i + 2)
+        } else if rest.starts_with("%{") {
+            rest.find("%}").map(|i| i + 2)
+        } else if c == '\x01' {
+            rest[1..].find('\x01').map(|i| i + 2)
+        } else if let Some(csi) = rest.strip_prefix("\x1B[") {
+            csi.find(|c: char| ('\x40'..='\x7E').contains(&c))
+                .map(|i| i + 3)
+        } else if rest.starts_with("\x1B]") {
+            rest.find('\x07')
+                .map(|i| i + 1)
+                .or_else(|| rest.find("\x1B\\").map(|i| i + 2))
+        } else {
+            None
+        };
+
+        let (len, shown) = match hidden {
+            Some(len) => (len, false),
+            None => (c.len_utf8(), true),
+        };
+        retval.push((&rest[..len], shown));
+        rest = &rest[len..];
+    }
+
+    retval
+}
+
+fn char_width(s: &str) -> usize {
+    s.chars().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+/// Returns the number of columns `s` takes up once printed
+pub(crate) fn display_width(s: &str) -> usize {
+    pieces(s)
+        .into_iter()
+        .filter(|&(_, shown)| shown)
+        .map(|(p, _)| char_width(p))
+        .sum()
+}
+
+/// Returns `s` without escape sequences and non-printing markers
+pub(crate) fn plain_text(s: &str) -> String {
+    pieces(s)
+        .into_iter()
+        .filter(|&(_, shown)| shown)
+        .map(|(p, _)| p)
+        .collect()
+}
+
+/// Shortens `s` to at most `width` columns, ending it with `ellipsis`
+///
+/// Escape sequences are kept so that styles are still reset afterwards.
+pub(crate) fn truncate(s: &str, width: usize, ellipsis: &str) -> String {
+    if display_width(s) <= width {
+        return s.to_owned();
+    }
+
+    let budget = width.saturating_sub(char_width(ellipsis));
+    let mut used = 0;
+    let mut retval = String::new();
+    let mut ended = false;
+
+    for (p, shown) in pieces(s) {
+        if !shown {
+            retval.push_str(p);
+        } else if !ended {
+            if used + char_width(p) > budget {
+                retval.push_str(ellipsis);
+                ended = true;
+            } else {
+                used += char_width(p);
+                retval.push_str(p);
+            }
+        }
+    }
+
+    retval
+}
+
+fn tty_columns(fd: libc::c_int) -> Option<usize> {
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            Some(usize::from(size.ws_col))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the width of the terminal from `COLUMNS`, or by asking whichever
+/// of stderr, stdin or stdout is connected to it
+pub(crate) fn terminal_columns() -> Option<usize> {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .or_else(|| [2, 0, 1].iter().filter_map(|&fd| tty_columns(fd)).next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_escapes() {
+        assert_eq!(display_width("\x1B[1;35mabc\x1B[0m"), 3);
+        assert_eq!(display_width("\\[\x1B[35m\\]abc\\[\x1B[0m\\]"), 3);
+        assert_eq!(display_width("%{\x1B[35m%}abc%{\x1B[0m%}"), 3);
+        assert_eq!(display_width("\x01\x1B[35m\x01abc"), 3);
+        assert_eq!(display_width("\x1B]8;;file:///\x07abc\x1B]8;;\x07"), 3);
+    }
+
+    #[test]
+    fn counts_wide_characters() {
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(display_width("├─┤"), 3);
+    }
+
+    #[test]
+    fn truncates() {
+        assert_eq!(truncate("abcdef", 6, "…"), "abcdef");
+        assert_eq!(truncate("abcdefg", 6, "…"), "abcde…");
+        assert_eq!(truncate("abcdefg", 6, "..."), "abc...");
+        assert_eq!(
+            truncate("\x1B[35mabcdefg\x1B[0m", 4, "…"),
+            "\x1B[35mabc…\x1B[0m"
+        );
+        assert_eq!(truncate("日本語", 4, "…"), "日…");
+    }
+}
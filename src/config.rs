@@ -0,0 +1,480 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The plugins the prompt is built from, read from
+//! `~/.config/megaprompt/config.toml`:
+//!
+//! ```toml
+//! plugins = ["host", "exit_status", "duration", "git"]
+//!
+//! [options.git]
+//! outgoing = false
+//! ```
+//!
+//! Plugins run in the order they are listed, and plugins that aren't listed
+//! don't run. Without a config file every plugin runs.
+//!
+//! A `.megaprompt.toml` in the working directory or any directory above it
+//! changes the config further down the tree, once it is trusted with
+//! `megaprompt trust`. They take the same keys as the config file, plus
+//! `disable` to turn off some of the plugins and `theme`.
+//!
+//! `[external.NAME]` defines a plugin that runs a command, see `external`.
+
+use due_date::{DueDatePlugin, PathTraversal};
+use duration::DurationPlugin;
+use exit_status::ExitStatusPlugin;
+use external::{ExternalOptions, ExternalPlugin};
+use git::GitPlugin;
+use host::HostPlugin;
+use jobs::JobsPlugin;
+use label::LabelPlugin;
+use prompt_buffer::{
+    PromptBuffer, PromptBufferPlugin, PromptContext, PromptLines, RenderOptions, ShellType,
+};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use toml;
+use trust;
+
+/// The name of per-directory config files
+pub const DIR_FILE: &str = ".megaprompt.toml";
+
+type Plugin = Box<dyn PromptBufferPlugin + Send>;
+type MakePlugin = fn(Option<&toml::Value>) -> Result<Plugin, String>;
+
+/// The options of plugins that don't have any
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct NoOptions {}
+
+/// Parses the options of a plugin, which all have defaults
+fn options<T: DeserializeOwned + Default>(options: Option<&toml::Value>) -> Result<T, String> {
+    match options {
+        Some(o) => o
+            .clone()
+            .try_into()
+            .map_err(|e| format!("{}", e).trim().to_owned()),
+        None => Ok(T::default()),
+    }
+}
+
+/// Every plugin, in the order they run by default
+const PLUGINS: [(&str, MakePlugin); 7] = [
+    ("label", |o| {
+        Ok(Box::new(LabelPlugin::with_options(options(o)?)))
+    }),
+    ("host", |o| {
+        Ok(Box::new(HostPlugin::with_options(options(o)?)))
+    }),
+    ("exit_status", |o| {
+        options::<NoOptions>(o)?;
+        Ok(Box::new(ExitStatusPlugin::new()))
+    }),
+    ("duration", |o| {
+        Ok(Box::new(DurationPlugin::with_options(options(o)?)))
+    }),
+    ("jobs", |o| {
+        options::<NoOptions>(o)?;
+        Ok(Box::new(JobsPlugin::new()))
+    }),
+    ("due_date", |o| {
+        Ok(Box::new(DueDatePlugin::with_options(options(o)?)))
+    }),
+    ("git", |o| {
+        Ok(Box::new(GitPlugin::with_options(options(o)?)))
+    }),
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    plugins: Option<Vec<String>>,
+    #[serde(default)]
+    disable: Vec<String>,
+    theme: Option<String>,
+    #[serde(default)]
+    options: toml::value::Table,
+    #[serde(default)]
+    external: toml::value::Table,
+}
+
+/// Which plugins run, in what order, and their options
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    plugins: Vec<String>,
+    options: toml::value::Table,
+    external: BTreeMap<String, ExternalOptions>,
+    theme: Option<String>,
+
+    /// Problems with the config files, shown in the prompt
+    notices: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            plugins: PLUGINS.iter().map(|&(name, _)| name.to_owned()).collect(),
+            options: toml::value::Table::new(),
+            external: BTreeMap::new(),
+            theme: None,
+            notices: Vec::new(),
+        }
+    }
+}
+
+/// Shows what went wrong with the config files
+struct NoticePlugin {
+    notices: Vec<String>,
+}
+
+impl PromptBufferPlugin for NoticePlugin {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        let style = lines.style("config.notice");
+        for notice in &self.notices {
+            lines.push(context.shell.new_line().styled_block(notice, style).build());
+        }
+    }
+}
+
+fn builtin(name: &str) -> Option<MakePlugin> {
+    PLUGINS
+        .iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, make)| make)
+}
+
+impl Config {
+    /// Returns the directory megaprompt keeps its files in
+    pub fn dir() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+            .map(|d| d.join("megaprompt"))
+    }
+
+    /// Returns where the config file is looked for
+    pub fn path() -> Option<PathBuf> {
+        Config::dir().map(|d| d.join("config.toml"))
+    }
+
+    /// Returns when the config file was last changed, if there is one
+    pub fn modified() -> Option<SystemTime> {
+        fs::metadata(Config::path()?)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Reads the config file, if there is one
+    pub fn load() -> Result<Config, String> {
+        let path = match Config::path() {
+            Some(p) if p.exists() => p,
+            _ => return Ok(Config::default()),
+        };
+        let s = fs::read_to_string(&path)
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        Config::parse(&s).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Reads the config file, falling back to the default plugins if it is
+    /// broken
+    pub fn load_or_default() -> Config {
+        Config::load().unwrap_or_else(Config::broken)
+    }
+
+    /// Returns the default config, with a notice about what is wrong with
+    /// the config file
+    fn broken(e: String) -> Config {
+        warn!("Using the default plugins: {}", e);
+        Config {
+            notices: vec![format!("{}, using the default plugins", e)],
+            ..Config::default()
+        }
+    }
+
+    /// Parses a config file, checking that every plugin exists and likes its
+    /// options
+    pub fn parse(s: &str) -> Result<Config, String> {
+        Config::default().merge(s)
+    }
+
+    /// Returns this config changed by the config file `s`
+    pub fn merge(&self, s: &str) -> Result<Config, String> {
+        let file: ConfigFile = toml::from_str(s).map_err(|e| format!("{}", e).trim().to_owned())?;
+        let mut config = self.clone();
+        for (name, value) in file.external {
+            if builtin(&name).is_some() {
+                return Err(format!(
+                    "external plugin `{}` is named like a built-in one",
+                    name
+                ));
+            }
+            let options = value.try_into().map_err(|e| {
+                format!("external plugin `{}`: {}", name, e)
+                    .trim()
+                    .to_owned()
+            })?;
+            // New external plugins run after the others unless the file
+            // says where
+            if config.external.insert(name.clone(), options).is_none() && file.plugins.is_none() {
+                config.plugins.push(name);
+            }
+        }
+        if let Some(ref plugins) = file.plugins {
+            config.plugins = plugins.clone();
+        }
+        for name in &file.disable {
+            config.check_name(name)?;
+        }
+        let disable = &file.disable;
+        config.plugins.retain(|p| !disable.contains(p));
+        for (name, value) in file.options {
+            match (config.options.get_mut(&name), value) {
+                (Some(&mut toml::Value::Table(ref mut old)), toml::Value::Table(new)) => {
+                    old.extend(new)
+                }
+                (_, value) => {
+                    config.options.insert(name, value);
+                }
+            }
+        }
+        if file.theme.is_some() {
+            config.theme = file.theme;
+        }
+
+        for name in config.plugins.iter().chain(config.options.keys()) {
+            config.plugin(name)?;
+        }
+        Ok(config)
+    }
+
+    /// Returns the config for prompts in `dir`, with the trusted
+    /// `.megaprompt.toml` files above it applied from the top down
+    pub fn for_dir(&self, dir: &Path) -> Config {
+        let mut files = PathTraversal::new(dir)
+            .map(|d| d.join(DIR_FILE))
+            .filter(|f| f.is_file())
+            .collect::<Vec<_>>();
+        files.reverse();
+
+        let trusted = trust::trusted();
+        let mut config = self.clone();
+        for file in files {
+            let s = match fs::read_to_string(&file) {
+                Ok(s) => s,
+                Err(e) => {
+                    config
+                        .notices
+                        .push(format!("Unable to read {}: {}", file.display(), e));
+                    continue;
+                }
+            };
+            if !trust::is_trusted(&trusted, &file, &s) {
+                config.notices.push(format!(
+                    "Ignoring {}, run `megaprompt trust` there to use it",
+                    file.display()
+                ));
+                continue;
+            }
+            match config.merge(&s) {
+                Ok(merged) => config = merged,
+                Err(e) => config.notices.push(format!("{}: {}", file.display(), e)),
+            }
+        }
+
+        config
+    }
+
+    /// Applies the theme of the config, which wins over `--theme` and
+    /// `$MEGAPROMPT_THEME`
+    pub fn apply(&self, options: &mut RenderOptions) {
+        if self.theme.is_some() {
+            options.theme = self.theme.clone();
+        }
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), String> {
+        if builtin(name).is_some() || self.external.contains_key(name) {
+            return Ok(());
+        }
+
+        let names = PLUGINS
+            .iter()
+            .map(|&(n, _)| n)
+            .chain(self.external.keys().map(|n| n.as_ref()))
+            .collect::<Vec<_>>();
+        Err(format!(
+            "unknown plugin `{}`, expected one of {}",
+            name,
+            names.join(", ")
+        ))
+    }
+
+    fn plugin(&self, name: &str) -> Result<Plugin, String> {
+        if let Some(options) = self.external.get(name) {
+            if self.options.contains_key(name) {
+                return Err(format!(
+                    "`{}` is an external plugin, its options go in [external.{}]",
+                    name, name
+                ));
+            }
+            return Ok(Box::new(ExternalPlugin::new(name, options.clone())));
+        }
+
+        self.check_name(name)?;
+        let make = builtin(name).expect("checked above");
+        make(self.options.get(name)).map_err(|e| format!("options of `{}`: {}", name, e))
+    }
+
+    /// Builds a prompt out of the configured plugins
+    pub fn prompt(&self, shell: ShellType) -> PromptBuffer {
+        let mut buf = PromptBuffer::new(shell);
+        if !self.notices.is_empty() {
+            buf.add_plugin(NoticePlugin {
+                notices: self.notices.clone(),
+            });
+        }
+        for name in &self.plugins {
+            match self.plugin(name) {
+                Ok(plugin) => buf.add_boxed_plugin(plugin),
+                Err(e) => warn!("Skipping plugin: {}", e),
+            }
+        }
+
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_plugins_in_order() {
+        let config = Config::parse(
+            r#"
+            plugins = ["git", "exit_status"]
+
+            [options.git]
+            outgoing = false
+
+            [options.duration]
+            threshold = 10
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.plugins, ["git", "exit_status"]);
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn reports_mistakes() {
+        assert_eq!(
+            Config::parse(r#"plugins = ["gti"]"#),
+            Err(
+                "unknown plugin `gti`, expected one of label, host, exit_status, \
+                 duration, jobs, due_date, git"
+                    .to_owned()
+            )
+        );
+        assert!(Config::parse("[options.git]\nstatuss = true")
+            .unwrap_err()
+            .starts_with("options of `git`: unknown field `statuss`"));
+        assert!(Config::parse("[options.duration]\nthreshold = \"long\"")
+            .unwrap_err()
+            .starts_with("options of `duration`: invalid type"));
+        assert!(Config::parse("[options.jobs]\nmax = 3")
+            .unwrap_err()
+            .starts_with("options of `jobs`"));
+        assert!(Config::parse("plugin = []").is_err());
+        assert!(Config::parse(r#"disable = ["gti"]"#).is_err());
+    }
+
+    #[test]
+    fn defines_external_plugins() {
+        let config = Config::parse(
+            r#"
+            [external.tests]
+            command = "ci-status"
+            timeout = 200
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.plugins.last().map(|p| p.as_ref()), Some("tests"));
+
+        let disabled = config.merge(r#"disable = ["tests"]"#).unwrap();
+        assert!(!disabled.plugins.contains(&"tests".to_owned()));
+
+        assert!(Config::parse("[external.git]\ncommand = \"git\"")
+            .unwrap_err()
+            .contains("built-in"));
+        assert!(Config::parse("[external.tests]\ntimeout = 200")
+            .unwrap_err()
+            .starts_with("external plugin `tests`: missing field `command`"));
+        assert!(config
+            .merge("[options.tests]\nverbose = true")
+            .unwrap_err()
+            .contains("[external.tests]"));
+    }
+
+    #[test]
+    fn broken_config_files_show_up_in_the_prompt() {
+        let broken = Config::broken("config.toml: unknown plugin `gti`".to_owned());
+        assert_eq!(broken.plugins, Config::default().plugins);
+
+        let mut lines = PromptLines::new();
+        let context = PromptContext::new(ShellType::Fish, PathBuf::from("/"));
+        NoticePlugin {
+            notices: broken.notices.clone(),
+        }
+        .run(&context, &mut lines);
+        assert_eq!(
+            lines[0].parts[0].plain_text(),
+            "config.toml: unknown plugin `gti`, using the default plugins"
+        );
+    }
+
+    #[test]
+    fn directories_change_the_config() {
+        let user = Config::parse(
+            r#"
+            plugins = ["label", "exit_status", "git"]
+
+            [options.git]
+            outgoing = false
+            "#,
+        )
+        .unwrap();
+        let monorepo = user
+            .merge(
+                r#"
+                disable = ["git"]
+                theme = "solarized"
+
+                [options.label]
+                text = "prod"
+
+                [options.git]
+                status = false
+                "#,
+            )
+            .unwrap();
+
+        assert_eq!(monorepo.plugins, ["label", "exit_status"]);
+        assert_eq!(monorepo.theme, Some("solarized".to_owned()));
+        assert_eq!(
+            monorepo.options["git"],
+            toml::from_str::<toml::Value>("outgoing = false\nstatus = false").unwrap()
+        );
+    }
+}
@@ -8,27 +8,46 @@
 
 extern crate time;
 
-use prompt_buffer::{PluginSpeed, PromptBufferPlugin, PromptLines, ShellType};
+use period;
+use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use term::color;
+
+/// Options of the `due_date` plugin
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DueDateOptions {
+    /// Name of the files holding due dates
+    file: String,
+}
+
+impl Default for DueDateOptions {
+    fn default() -> DueDateOptions {
+        DueDateOptions {
+            file: ".due".to_owned(),
+        }
+    }
+}
 
 #[derive(Default)]
-pub struct DueDatePlugin;
+pub struct DueDatePlugin {
+    options: DueDateOptions,
+}
 
 impl DueDatePlugin {
-    pub fn new() -> DueDatePlugin {
-        DueDatePlugin::default()
+    pub fn with_options(options: DueDateOptions) -> DueDatePlugin {
+        DueDatePlugin { options }
     }
 }
 
-struct PathTraversal {
+/// Iterates over a directory and the directories above it
+pub struct PathTraversal {
     path: PathBuf,
 }
 
 impl PathTraversal {
-    fn new(p: &Path) -> PathTraversal {
+    pub fn new(p: &Path) -> PathTraversal {
         let mut pat: PathBuf = p.into();
         pat.push("dummy");
         PathTraversal { path: pat }
@@ -46,38 +65,11 @@ impl Iterator for PathTraversal {
     }
 }
 
-struct TimePeriod {
-    singular: String,
-    plural: String,
-}
-
-trait ToTimePeriod {
-    fn as_period(&self) -> TimePeriod;
-}
-
-impl ToTimePeriod for str {
-    fn as_period(&self) -> TimePeriod {
-        let mut p = self.to_owned();
-        p.push('s');
-
-        (self, p.as_ref()).as_period()
-    }
-}
-
-impl<'s> ToTimePeriod for (&'s str, &'s str) {
-    fn as_period(&self) -> TimePeriod {
-        let (s, p) = *self;
-        TimePeriod {
-            singular: s.to_owned(),
-            plural: p.to_owned(),
-        }
-    }
-}
-
 impl PromptBufferPlugin for DueDatePlugin {
-    fn run(&mut self, _: PluginSpeed, shell: ShellType, path: &Path, lines: &mut PromptLines) {
-        for mut path in PathTraversal::new(path) {
-            path.push(".due");
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        let shell = context.shell;
+        for mut path in PathTraversal::new(&context.path) {
+            path.push(&self.options.file);
 
             if path.is_file() {
                 let mut reader = BufReader::new(File::open(&path).expect("Unable to open file"));
@@ -105,69 +97,70 @@ impl PromptBufferPlugin for DueDatePlugin {
                     } else {
                         (s, false)
                     };
-                    let mut seconds: f32 = seconds.as_seconds_f32();
-
-                    let ups: [f32; 9] =
-                        [10.0, 10.0, 10.0, 365.0 / 30.0, 30.0, 24.0, 60.0, 60.0, 1.0];
-                    let time_periods = [
-                        ("millenium", "millenia").as_period(),
-                        ("century", "centuries").as_period(),
-                        "decade".as_period(),
-                        "year".as_period(),
-                        "month".as_period(),
-                        "day".as_period(),
-                        "hour".as_period(),
-                        "minute".as_period(),
-                        "second".as_period(),
-                    ];
-
-                    let times = (0..ups.len()).map(|i| ups[i..].iter().fold(1.0, |a, &b| a * b));
-
-                    let accuracy = 2u8;
-                    let mut count = 0u8;
-                    let mut due_phrase = String::new();
-
-                    for (amount, name) in times.zip(time_periods.iter()) {
-                        if seconds > amount {
-                            count += 1;
-                            let rem = seconds % amount;
-                            let amt = seconds / amount - (rem / amount);
-                            seconds = rem;
-                            let name = if amt > 1.0 {
-                                &name.plural
-                            } else {
-                                &name.singular
-                            };
-                            due_phrase = format!("{}{} {} ", due_phrase, amt.round() as i32, name);
-                        }
-
-                        if count >= accuracy {
-                            break;
-                        }
-                    }
+                    let due_phrase = period::describe(seconds.as_seconds_f32(), 2);
 
                     let title = line("Project");
                     let future = line("is due in");
                     let past = line("was due");
-                    let (color, temporal, postfix) = if past_due {
-                        (color::RED, past, " ago")
+                    let (role, temporal, postfix) = if past_due {
+                        ("due.overdue", past, " ago")
                     } else {
-                        (color::CYAN, future, "")
+                        ("due.upcoming", future, "")
                     };
 
-                    due_phrase = format!(
-                        "{}{} {}: {}{}{}",
-                        shell.col(color::MAGENTA),
-                        title.trim(),
-                        temporal.trim(),
-                        shell.col(color),
-                        due_phrase.trim(),
-                        postfix
+                    let title_style = lines.style("due.title");
+                    let phrase_style = lines.style(role);
+                    lines.push(
+                        shell
+                            .new_line()
+                            .styled_block(
+                                format!("{} {}:", title.trim(), temporal.trim()),
+                                title_style,
+                            )
+                            .styled_block(format!("{}{}", due_phrase, postfix), phrase_style)
+                            .build(),
                     );
-
-                    lines.push(shell.new_line().block(due_phrase).build());
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prompt_buffer::ShellType;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn draws_the_due_date() {
+        let dir = env::temp_dir().join(format!("megaprompt-due-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(".due"),
+            "Mon Jan 01 12:00:00 2024\nMyProj\nis due in\nwas due\n",
+        )
+        .unwrap();
+
+        for &shell in &[ShellType::Bash, ShellType::Zsh] {
+            let mut lines = PromptLines::new();
+            DueDatePlugin::default().run(&PromptContext::new(shell, dir.clone()), &mut lines);
+
+            assert_eq!(lines.len(), 1);
+            assert_eq!(lines[0].parts.len(), 2);
+            let title = lines[0].parts[0].plain_text();
+            assert!(title == "MyProj is due in:" || title == "MyProj was due:");
+            let drawn = lines[0]
+                .parts
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<String>();
+            assert!(drawn.contains("\x1b["));
+            assert!(drawn.contains("MyProj "));
+            assert!(!drawn.contains("\\\\["));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
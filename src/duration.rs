@@ -0,0 +1,83 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use period;
+use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines};
+use std::time::Duration;
+
+/// Options of the `duration` plugin
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DurationOptions {
+    /// Commands that finish quicker than this many seconds aren't worth
+    /// mentioning
+    threshold: f32,
+}
+
+impl Default for DurationOptions {
+    fn default() -> DurationOptions {
+        DurationOptions { threshold: 5.0 }
+    }
+}
+
+/// Shows how long the last command took, if it took a while
+#[derive(Default)]
+pub struct DurationPlugin {
+    options: DurationOptions,
+}
+
+impl DurationPlugin {
+    pub fn with_options(options: DurationOptions) -> DurationPlugin {
+        DurationPlugin { options }
+    }
+}
+
+fn describe(duration: Duration, threshold: f32) -> Option<String> {
+    if duration.as_secs_f32() < threshold {
+        return None;
+    }
+
+    Some(format!(
+        "took {}",
+        period::describe(duration.as_secs_f32(), 2)
+    ))
+}
+
+impl PromptBufferPlugin for DurationPlugin {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        let threshold = self.options.threshold;
+        if let Some(text) = context.state.duration.and_then(|d| describe(d, threshold)) {
+            let style = lines.style("duration");
+            lines.push(context.shell.new_line().styled_block(text, style).build());
+        }
+    }
+
+    fn uses_state(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+    use std::time::Duration;
+
+    #[test]
+    fn describes_long_commands() {
+        assert_eq!(describe(Duration::from_millis(4_999), 5.0), None);
+        assert_eq!(
+            describe(Duration::from_secs(5), 5.0),
+            Some("took 5 seconds".to_owned())
+        );
+        assert_eq!(
+            describe(Duration::from_millis(125_300), 5.0),
+            Some("took 2 minutes 5 seconds".to_owned())
+        );
+        assert_eq!(describe(Duration::from_secs(5), 10.0), None);
+    }
+}
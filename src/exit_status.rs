@@ -0,0 +1,62 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines, ShellState};
+
+/// Shows the exit status of the last command when it failed
+#[derive(Default)]
+pub struct ExitStatusPlugin;
+
+impl ExitStatusPlugin {
+    pub fn new() -> ExitStatusPlugin {
+        ExitStatusPlugin
+    }
+}
+
+fn describe(state: &ShellState) -> Option<String> {
+    if !state.failed() {
+        return None;
+    }
+
+    let status = state.exit_status?;
+    Some(match state.signal_name() {
+        Some(signal) => format!("✘ {} {}", status, signal),
+        None => format!("✘ {}", status),
+    })
+}
+
+impl PromptBufferPlugin for ExitStatusPlugin {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        if let Some(text) = describe(&context.state) {
+            let style = lines.style("status.failure");
+            lines.push(context.shell.new_line().styled_block(text, style).build());
+        }
+    }
+
+    fn uses_state(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+    use prompt_buffer::ShellState;
+
+    #[test]
+    fn describes_failures() {
+        let state = |s| ShellState {
+            exit_status: Some(s),
+            ..ShellState::default()
+        };
+        assert_eq!(describe(&state(0)), None);
+        assert_eq!(describe(&ShellState::default()), None);
+        assert_eq!(describe(&state(1)), Some("✘ 1".to_owned()));
+        assert_eq!(describe(&state(130)), Some("✘ 130 SIGINT".to_owned()));
+    }
+}
@@ -0,0 +1,508 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Plugins written in any language
+//!
+//! An external plugin is an executable that reads the prompt context as JSON
+//! on stdin and writes the lines to add in the same JSON as `--format json`:
+//!
+//! ```json
+//! {"lines": [{"level": 0, "type": "boxed",
+//!             "boxes": [{"text": "3 failing tests", "color": "red", "bold": true}]}],
+//!  "right": [{"text": "k8s: prod", "role": "label"}]}
+//! ```
+//!
+//! It runs in the working directory with the environment of the shell, in a
+//! process group of its own so that it can be killed along with anything it
+//! started.
+
+use libc;
+use prompt_buffer::color::Color;
+use prompt_buffer::{PluginSpeed, PromptBufferPlugin, PromptContext, PromptLines, Style};
+use serde_json;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a command may take when the prompt is drawn without waiting
+const FAST_TIMEOUT: u64 = 20;
+
+/// Seconds an output is kept for prompts drawn without waiting, as long as
+/// an idle prompt thread lives
+const KEEP: u64 = 10 * 60;
+
+fn default_timeout() -> u64 {
+    500
+}
+
+/// Definition of an external plugin, from `[external.NAME]`
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields)]
+pub struct ExternalOptions {
+    /// The executable, looked up in `$PATH` unless it is a path
+    command: String,
+
+    #[serde(default)]
+    args: Vec<String>,
+
+    /// Milliseconds the command may take before it is killed
+    #[serde(default = "default_timeout")]
+    timeout: u64,
+
+    /// Seconds the output is reused for in the same directory
+    #[serde(default)]
+    cache: u64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct ExternalBox {
+    text: String,
+    color: Option<String>,
+    background: Option<String>,
+    #[serde(default)]
+    bold: bool,
+    link: Option<String>,
+
+    /// A theme role to start from instead of the default style
+    role: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+enum LineType {
+    #[default]
+    Boxed,
+    Free,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct ExternalLine {
+    #[serde(default)]
+    level: u8,
+    #[serde(default, rename = "type")]
+    line_type: LineType,
+    #[serde(default)]
+    boxes: Vec<ExternalBox>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+struct ExternalOutput {
+    #[serde(default)]
+    lines: Vec<ExternalLine>,
+    #[serde(default)]
+    right: Vec<ExternalBox>,
+}
+
+enum RunError {
+    TimedOut,
+    Failed(String),
+}
+
+type Cache = HashMap<(String, ExternalOptions, PathBuf), (Instant, ExternalOutput)>;
+
+/// The last output of every external plugin in every directory, kept across
+/// config reloads that leave the plugin as it was
+fn cache() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+fn parse_color(color: &Option<String>) -> Result<Option<Color>, String> {
+    match *color {
+        Some(ref c) => c.parse().map(Some),
+        None => Ok(None),
+    }
+}
+
+impl ExternalBox {
+    fn style(&self, lines: &PromptLines) -> Result<Style, String> {
+        let mut style = match self.role {
+            Some(ref role) => lines.style(role),
+            None => Style::default(),
+        };
+        if let Some(c) = parse_color(&self.color)? {
+            style.fg = Some(c);
+        }
+        if let Some(c) = parse_color(&self.background)? {
+            style.bg = Some(c);
+        }
+        style.bold |= self.bold;
+        Ok(style)
+    }
+}
+
+/// Kills the command and everything it started
+fn kill(child: &mut Child) {
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+/// Runs a configured executable and draws what it prints
+pub struct ExternalPlugin {
+    name: String,
+    options: ExternalOptions,
+}
+
+impl ExternalPlugin {
+    pub fn new(name: &str, options: ExternalOptions) -> ExternalPlugin {
+        ExternalPlugin {
+            name: name.to_owned(),
+            options,
+        }
+    }
+
+    fn input(context: &PromptContext) -> String {
+        let speed = match context.speed {
+            PluginSpeed::Ignored => "ignored",
+            PluginSpeed::Fast => "fast",
+            PluginSpeed::Slow => "slow",
+        };
+        serde_json::json!({
+            "shell": format!("{:?}", context.shell).to_lowercase(),
+            "path": context.path.to_string_lossy(),
+            "speed": speed,
+            "columns": context.columns,
+            "status": context.state.exit_status,
+            "duration": context.state.duration.map(|d| d.as_millis() as u64),
+            "jobs": context.state.jobs,
+            "user": context.user,
+            "uid": context.uid,
+        })
+        .to_string()
+    }
+
+    /// Runs the command, killing it if it hasn't exited after `budget`
+    fn execute(
+        &self,
+        context: &PromptContext,
+        budget: Duration,
+    ) -> Result<ExternalOutput, RunError> {
+        let deadline = Instant::now() + budget;
+        let command = &self.options.command;
+        let mut child = Command::new(command)
+            .args(&self.options.args)
+            .current_dir(&context.path)
+            .env_clear()
+            .envs(&context.env)
+            .process_group(0)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| RunError::Failed(format!("unable to run `{}`: {}", command, e)))?;
+
+        let input = ExternalPlugin::input(context);
+        let (mut stdin, mut stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => return Err(RunError::Failed("unable to talk to the command".to_owned())),
+        };
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            // Commands that don't care about the context close stdin early
+            let _ = stdin.write_all(input.as_bytes());
+            drop(stdin);
+            let mut out = String::new();
+            let _ = tx.send(stdout.read_to_string(&mut out).map(|_| out));
+        });
+
+        let out = match rx.recv_timeout(budget) {
+            Ok(out) => {
+                out.map_err(|e| RunError::Failed(format!("unable to read output: {}", e)))?
+            }
+            Err(_) => {
+                kill(&mut child);
+                return Err(RunError::TimedOut);
+            }
+        };
+        // Closing stdout doesn't mean the command is done
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(1)),
+                Ok(None) => {
+                    kill(&mut child);
+                    return Err(RunError::TimedOut);
+                }
+                Err(e) => return Err(RunError::Failed(format!("`{}` failed: {}", command, e))),
+            }
+        };
+        if !status.success() {
+            return Err(RunError::Failed(format!(
+                "`{}` failed, {}",
+                command, status
+            )));
+        }
+
+        serde_json::from_str(&out).map_err(|e| RunError::Failed(format!("bad output: {}", e)))
+    }
+
+    /// Returns the output of the command, from the cache when it is fresh
+    /// enough or when there is no time to run it
+    fn output(&self, context: &PromptContext) -> Result<ExternalOutput, String> {
+        let key = (
+            self.name.clone(),
+            self.options.clone(),
+            context.path.clone(),
+        );
+        let cached = cache()
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|&&(at, _)| at.elapsed().as_secs() < KEEP)
+            .cloned();
+        let budget = match (context.speed, cached.clone()) {
+            (PluginSpeed::Ignored, _) => return Ok(ExternalOutput::default()),
+            (PluginSpeed::Fast, Some((_, output))) => return Ok(output),
+            (_, Some((at, output))) if at.elapsed().as_secs() < self.options.cache => {
+                return Ok(output)
+            }
+            (PluginSpeed::Fast, None) => self.options.timeout.min(FAST_TIMEOUT),
+            (PluginSpeed::Slow, _) => self.options.timeout,
+        };
+
+        match self.execute(context, Duration::from_millis(budget)) {
+            Ok(output) => {
+                let mut cache = cache().lock().unwrap();
+                cache.retain(|_, &mut (at, _)| at.elapsed().as_secs() < KEEP);
+                cache.insert(key, (Instant::now(), output.clone()));
+                Ok(output)
+            }
+            Err(RunError::TimedOut) => match cached {
+                Some((_, output)) => Ok(output),
+                None if context.speed == PluginSpeed::Fast => Ok(ExternalOutput::default()),
+                None => Err(format!("took longer than {}ms", budget)),
+            },
+            Err(RunError::Failed(e)) => Err(e),
+        }
+    }
+
+    fn draw(
+        &self,
+        output: &ExternalOutput,
+        context: &PromptContext,
+        lines: &mut PromptLines,
+    ) -> Result<(), String> {
+        let shell = context.shell;
+        let mut built = Vec::new();
+        for line in &output.lines {
+            let mut builder = match line.line_type {
+                LineType::Boxed => shell.new_line(),
+                LineType::Free => shell.new_free_line(),
+            }
+            .indent_by(line.level);
+            for b in &line.boxes {
+                builder = builder.styled_block(&b.text, b.style(lines)?);
+                if let Some(ref link) = b.link {
+                    builder = builder.links_to(link);
+                }
+            }
+            built.push(builder.build());
+        }
+        let mut right = shell.new_line();
+        for b in &output.right {
+            right = right.styled_block(&b.text, b.style(lines)?);
+        }
+
+        for line in built {
+            lines.push(line);
+        }
+        if !output.right.is_empty() {
+            lines.push_right(right.build());
+        }
+        Ok(())
+    }
+}
+
+impl PromptBufferPlugin for ExternalPlugin {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        if let Err(e) = self
+            .output(context)
+            .and_then(|output| self.draw(&output, context, lines))
+        {
+            warn!("External plugin `{}`: {}", self.name, e);
+            let style = lines.style("config.notice");
+            lines.push(
+                context
+                    .shell
+                    .new_line()
+                    .styled_block(format!("{}: {}", self.name, e), style)
+                    .build(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prompt_buffer::ShellType;
+    use std::env;
+    use std::fs;
+
+    fn plugin(name: &str, script: &str, timeout: u64, cache: u64) -> ExternalPlugin {
+        ExternalPlugin::new(
+            name,
+            ExternalOptions {
+                command: format!(
+                    "{}/tests/fixtures/external/{}",
+                    env!("CARGO_MANIFEST_DIR"),
+                    script
+                ),
+                args: Vec::new(),
+                timeout,
+                cache,
+            },
+        )
+    }
+
+    fn run(plugin: &mut ExternalPlugin, context: &PromptContext) -> Vec<String> {
+        let mut lines = PromptLines::new();
+        plugin.run(context, &mut lines);
+        lines
+            .iter()
+            .map(|l| {
+                l.parts
+                    .iter()
+                    .map(|p| p.plain_text())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    fn context() -> PromptContext {
+        PromptContext::new(ShellType::Bash, env::temp_dir())
+    }
+
+    #[test]
+    fn draws_what_commands_print() {
+        let mut hello = plugin("hello", "hello.sh", 5000, 0);
+        let mut context = context();
+        context.env.insert("GREETING".to_owned(), "hi".to_owned());
+
+        let mut lines = PromptLines::new();
+        hello.run(&context, &mut lines);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].parts[0].plain_text(), "hi from slow");
+        assert!(lines[0].parts[0].is_bold());
+        assert_eq!(lines[0].parts[0].color(), "green".parse().ok());
+        assert_eq!(lines[1].level, 1);
+        assert_eq!(lines.right()[0].plain_text(), "right");
+    }
+
+    #[test]
+    fn sends_any_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let context = PromptContext::new(
+            ShellType::Bash,
+            PathBuf::from(OsStr::from_bytes(b"/tmp/caf\xe9")),
+        );
+        assert!(ExternalPlugin::input(&context).contains("\"path\":\"/tmp/caf\u{fffd}\""));
+    }
+
+    #[test]
+    fn gives_up_on_slow_commands() {
+        let mut slow = plugin("slow", "slow.sh", 100, 0);
+        let mut context = context();
+        let start = Instant::now();
+        assert_eq!(run(&mut slow, &context), ["slow: took longer than 100ms"]);
+
+        context.speed = PluginSpeed::Fast;
+        assert!(run(&mut slow, &context).is_empty());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Waits up to a second for a killed process to be gone, or a zombie
+    /// waiting for init
+    fn is_gone(pid: &str) -> bool {
+        let stat = PathBuf::from("/proc").join(pid.trim()).join("stat");
+        (0..100).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            fs::read_to_string(&stat)
+                .map(|s| s.contains(") Z "))
+                .unwrap_or(true)
+        })
+    }
+
+    #[test]
+    fn kills_what_commands_leave_behind() {
+        let pid_file = env::temp_dir().join(format!("megaprompt-pid-{}", std::process::id()));
+        let mut context = context();
+        context
+            .env
+            .insert("PID_FILE".to_owned(), pid_file.display().to_string());
+
+        let start = Instant::now();
+        let mut detached = plugin("detached", "detached.sh", 200, 0);
+        assert_eq!(
+            run(&mut detached, &context),
+            ["detached: took longer than 200ms"]
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let pid = fs::read_to_string(&pid_file).unwrap();
+        let _ = fs::remove_file(&pid_file);
+        assert!(is_gone(&pid));
+    }
+
+    #[test]
+    fn only_sees_the_shell_environment() {
+        let mut context = context();
+        context.env.insert("GREETING".to_owned(), "hi".to_owned());
+        assert_eq!(
+            run(&mut plugin("env", "env.sh", 5000, 0), &context),
+            ["GREETING=hi HOME=unset"]
+        );
+    }
+
+    #[test]
+    fn reuses_output_in_the_same_directory() {
+        let count = env::temp_dir().join(format!("megaprompt-count-{}", std::process::id()));
+        let _ = fs::remove_file(&count);
+        let mut context = context();
+        context
+            .env
+            .insert("COUNT_FILE".to_owned(), count.display().to_string());
+
+        let mut cached = plugin("count-cached", "count.sh", 5000, 60);
+        assert_eq!(run(&mut cached, &context), ["run 1"]);
+        assert_eq!(run(&mut cached, &context), ["run 1"]);
+
+        let mut uncached = plugin("count", "count.sh", 5000, 0);
+        assert_eq!(run(&mut uncached, &context), ["run 2"]);
+        context.speed = PluginSpeed::Fast;
+        assert_eq!(run(&mut uncached, &context), ["run 2"]);
+
+        // A plugin that was configured again doesn't get the old output
+        let mut changed = plugin("count", "count.sh", 4000, 0);
+        assert_ne!(run(&mut changed, &context), ["run 2"]);
+
+        let _ = fs::remove_file(&count);
+    }
+
+    #[test]
+    fn reports_broken_commands() {
+        let context = context();
+        let broken = run(&mut plugin("broken", "broken.sh", 5000, 0), &context);
+        assert_eq!(broken.len(), 1);
+        assert!(broken[0].starts_with("broken: bad output"));
+
+        let missing = run(&mut plugin("missing", "missing.sh", 5000, 0), &context);
+        assert!(missing[0].starts_with("missing: unable to run"));
+    }
+}
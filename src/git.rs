@@ -10,11 +10,13 @@ extern crate git2;
 // extern crate term;
 
 use git2::{Error, Repository, StatusOptions};
-use prompt_buffer::{PluginSpeed, PromptBufferPlugin, PromptLines, ShellType};
+use prompt_buffer::link;
+use prompt_buffer::{
+    PluginSpeed, PromptBufferPlugin, PromptContext, PromptLines, ShellType, Style,
+};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::{env, fmt};
-use term::color;
 
 trait RelativePath: Sized {
     fn make_relative(self, base: &Path) -> Option<Self>;
@@ -117,6 +119,58 @@ fn get_git(path: &Path) -> Option<Repository> {
     Repository::discover(path).ok()
 }
 
+/// Where commits link to, unless `megaprompt.commitUrl` is set in the git
+/// config. `{base}` is the web page of the remote, `{sha}` the commit id
+const DEFAULT_COMMIT_URL: &str = "{base}/commit/{sha}";
+
+/// Turns the URL of a remote into the address of its web page, such as
+/// `https://github.com/zmbush/megaprompt`
+fn web_url(remote: &str) -> Option<String> {
+    let remote = remote.trim_end_matches('/');
+    let remote = remote.strip_suffix(".git").unwrap_or(remote);
+    let (scheme, host, path) = match remote.split_once("://") {
+        Some((scheme, rest)) => {
+            let (host, path) = rest.split_once('/')?;
+            (scheme, host, path)
+        }
+        // scp-like syntax: git@github.com:zmbush/megaprompt
+        None => {
+            let (host, path) = remote.split_once(':')?;
+            ("ssh", host, path)
+        }
+    };
+
+    let host = host.rsplit('@').next().unwrap_or(host);
+    let host = match scheme {
+        "http" | "https" => host,
+        _ => host.split(':').next().unwrap_or(host),
+    };
+    if host.is_empty() || path.is_empty() {
+        return None;
+    }
+
+    Some(format!("https://{}/{}", host, path))
+}
+
+/// Returns the template of links to commits on the remote of `upstream`,
+/// with `{sha}` left to fill in
+fn commit_url(repo: &Repository, upstream: Option<&str>) -> Option<String> {
+    let template = repo
+        .config()
+        .and_then(|c| c.get_string("megaprompt.commitUrl"))
+        .unwrap_or_else(|_| DEFAULT_COMMIT_URL.to_owned());
+    if !template.contains("{base}") {
+        return Some(template);
+    }
+
+    let remote = upstream
+        .and_then(|u| u.split('/').next())
+        .and_then(|name| repo.find_remote(name).ok())
+        .or_else(|| repo.find_remote("origin").ok())?;
+    let base = web_url(remote.url()?)?;
+    Some(template.replace("{base}", &base))
+}
+
 struct BranchInfo {
     name: Option<String>,
     upstream: Option<String>,
@@ -168,9 +222,30 @@ fn git_branch(repo: &Repository) -> Result<BranchInfo, Error> {
     }
 }
 
+/// Options of the `git` plugin
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitOptions {
+    /// Lists changed files
+    status: bool,
+
+    /// Lists commits that haven't been pushed
+    outgoing: bool,
+}
+
+impl Default for GitOptions {
+    fn default() -> GitOptions {
+        GitOptions {
+            status: true,
+            outgoing: true,
+        }
+    }
+}
+
 pub struct GitPlugin {
     repo: Option<Repository>,
     path: PathBuf,
+    options: GitOptions,
 }
 
 impl Default for GitPlugin {
@@ -178,13 +253,17 @@ impl Default for GitPlugin {
         GitPlugin {
             repo: None,
             path: env::current_dir().expect("There is no current directory!"),
+            options: GitOptions::default(),
         }
     }
 }
 
 impl GitPlugin {
-    pub fn new() -> GitPlugin {
-        GitPlugin::default()
+    pub fn with_options(options: GitOptions) -> GitPlugin {
+        GitPlugin {
+            options,
+            ..GitPlugin::default()
+        }
     }
 
     fn get_repo(&self) -> Result<&Repository, Error> {
@@ -200,14 +279,15 @@ impl GitPlugin {
         buffer: &mut PromptLines,
         path: &Path,
     ) -> Result<bool, Error> {
-        fn file_state_color(state: &StatusTypes) -> u32 {
+        fn file_state_role(state: &StatusTypes) -> &'static str {
             match *state {
-                StatusTypes::Clean | StatusTypes::Untracked => color::WHITE,
-                StatusTypes::Deleted => color::RED,
-                StatusTypes::Modified => color::BLUE,
-                StatusTypes::New => color::GREEN,
-                StatusTypes::Renamed => color::CYAN,
-                StatusTypes::TypeChange => color::YELLOW,
+                StatusTypes::Clean => "git.status.clean",
+                StatusTypes::Untracked => "git.status.untracked",
+                StatusTypes::Deleted => "git.status.deleted",
+                StatusTypes::Modified => "git.status.modified",
+                StatusTypes::New => "git.status.new",
+                StatusTypes::Renamed => "git.status.renamed",
+                StatusTypes::TypeChange => "git.status.typechange",
             }
         }
 
@@ -219,13 +299,10 @@ impl GitPlugin {
                 .renames_head_to_index(true),
         ));
 
+        let workdir = repo.workdir().expect("Repo has no working dir");
         let make_path_relative = |current: &Path| {
-            let mut fullpath = repo
-                .workdir()
-                .expect("Repo has no working dir")
-                .to_path_buf();
-            fullpath.push(current);
-            fullpath
+            workdir
+                .join(current)
                 .make_relative(path)
                 .unwrap_or_else(|| PathBuf::from("/"))
         };
@@ -235,12 +312,8 @@ impl GitPlugin {
                 return Ok(false);
             }
 
-            buffer.push(
-                shell
-                    .new_line()
-                    .colored_block("Git Status", color::CYAN)
-                    .build(),
-            );
+            let header = buffer.style("git.header");
+            buffer.push(shell.new_line().styled_block("Git Status", header).build());
 
             for stat in statuses.iter() {
                 let mut line = shell.new_free_line();
@@ -251,6 +324,10 @@ impl GitPlugin {
                     Some(delta) => Some(delta),
                     None => stat.index_to_workdir(),
                 };
+                let target = match diff {
+                    Some(ref delta) => delta.new_file().path(),
+                    None => stat.path().map(Path::new),
+                };
 
                 let val = format!(
                     "{} {}",
@@ -275,17 +352,19 @@ impl GitPlugin {
                     }
                 );
 
-                line = match status.index {
-                    StatusTypes::Clean => {
-                        line.colored_block(val, file_state_color(&status.workdir))
-                    }
+                let style = match status.index {
+                    StatusTypes::Clean => buffer.style(file_state_role(&status.workdir)),
                     _ => match status.workdir {
                         StatusTypes::Clean | StatusTypes::Untracked => {
-                            line.bold_colored_block(val, file_state_color(&status.index))
+                            buffer.style(file_state_role(&status.index)).bold()
                         }
-                        _ => line.bold_colored_block(val, color::RED),
+                        _ => buffer.style("git.status.partial"),
                     },
                 };
+                line = line.styled_block(val, style);
+                if let Some(target) = target {
+                    line = line.links_to(link::file_url(&workdir.join(target)));
+                }
 
                 buffer.push(line.indent().build());
             }
@@ -305,6 +384,7 @@ impl GitPlugin {
         let repo = self.get_repo()?;
 
         let branches = git_branch(repo)?;
+        let commit_link = commit_url(repo, branches.upstream.as_deref());
 
         let mut revwalk = repo.revwalk()?;
 
@@ -334,33 +414,30 @@ impl GitPlugin {
             let commit = repo.find_commit(id)?;
 
             if !log_shown {
+                let header = buffer.style("git.header");
                 buffer.push(
                     shell
                         .new_line()
-                        .colored_block("Git Outgoing", color::CYAN)
+                        .styled_block("Git Outgoing", header)
                         .indent_by(if has_status { 1 } else { 0 })
                         .build(),
                 );
                 log_shown = true;
             }
 
-            buffer.push(
-                shell
-                    .new_free_line()
-                    .indent()
-                    .block(format!(
-                        "{}{} {}",
-                        shell.reset(),
-                        String::from_utf8_lossy(
-                            repo.find_object(commit.id(), None)?.short_id()?.deref()
-                        ),
-                        String::from_utf8_lossy(match commit.summary_bytes() {
-                            Some(b) => b,
-                            None => continue,
-                        })
-                    ))
-                    .build(),
+            let summary = match commit.summary_bytes() {
+                Some(b) => String::from_utf8_lossy(b).into_owned(),
+                None => continue,
+            };
+            let log = buffer.style("git.log");
+            let mut line = shell.new_free_line().indent().styled_block(
+                String::from_utf8_lossy(repo.find_object(commit.id(), None)?.short_id()?.deref()),
+                log,
             );
+            if let Some(ref template) = commit_link {
+                line = line.links_to(template.replace("{sha}", &commit.id().to_string()));
+            }
+            buffer.push(line.styled_block(summary, log).build());
         }
 
         Ok(log_shown)
@@ -375,49 +452,173 @@ impl GitPlugin {
         let repo = self.get_repo()?;
 
         let branches = git_branch(repo)?;
+        let upstream = buffer.style("git.upstream");
+
+        if let Some(name) = repo.workdir().and_then(|w| w.file_name()) {
+            buffer.set_title_field("repo", name.to_string_lossy());
+        }
+        if let Some(ref name) = branches.name {
+            buffer.set_title_field("branch", name);
+        }
 
-        buffer.push(
-            shell
+        let branch = buffer.style("git.branch");
+        let line = match (branches.name, branches.upstream) {
+            (None, None) => shell.new_line().styled_block("New Repository", branch),
+            (Some(name), None) => shell.new_line().styled_block(name, branch),
+            (Some(name), Some(remote)) => shell
                 .new_line()
-                .colored_block(
-                    match (branches.name, branches.upstream) {
-                        (None, None) => "New Repository".to_owned(),
-                        (Some(name), None) => name,
-                        (Some(name), Some(remote)) => format!(
-                            "{}{} -> {}{}",
-                            name,
-                            shell.reset(),
-                            shell.col(color::MAGENTA),
-                            remote
-                        ),
-                        _ => "Unknown branch state".to_owned(),
-                    },
-                    color::CYAN,
-                )
-                .indent_by(if indented { 1 } else { 0 })
-                .build(),
-        );
+                .styled_block(name, branch)
+                .append_styled(" -> ", Style::default())
+                .append_styled(remote, upstream),
+            _ => shell
+                .new_line()
+                .styled_block("Unknown branch state", branch),
+        };
+        buffer.push(line.indent_by(if indented { 1 } else { 0 }).build());
 
         Ok(true)
     }
 }
 
 impl PromptBufferPlugin for GitPlugin {
-    fn run(&mut self, speed: PluginSpeed, shell: ShellType, path: &Path, lines: &mut PromptLines) {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        let shell = context.shell;
+        let path = context.path.as_path();
         if self.path != *path || self.repo.is_none() {
             self.path = path.into();
             self.repo = get_git(&self.path);
         }
 
-        let st = match speed {
-            PluginSpeed::Slow => {
+        let st = match context.speed {
+            PluginSpeed::Slow if self.options.status => {
                 trace!("Finding git status");
                 self.status(shell, lines, path).ok().unwrap_or(false)
             }
             _ => false,
         };
-        trace!("Finding outgoing commits");
-        let out = self.outgoing(shell, lines, st).ok().unwrap_or(false);
+        let out = if self.options.outgoing {
+            trace!("Finding outgoing commits");
+            self.outgoing(shell, lines, st).ok().unwrap_or(false)
+        } else {
+            false
+        };
         let _ = self.end(shell, lines, st || out).ok();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{web_url, GitPlugin};
+    use git2::{Repository, Signature, Time};
+    use prompt_buffer::link;
+    use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines, ShellType};
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    fn commit(repo: &Repository, file: &str, message: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::new("Test", "test@example.com", &Time::new(0, 0)).unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents = parent.iter().collect::<Vec<_>>();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap();
+    }
+
+    #[test]
+    fn golden_json() {
+        let dir = env::temp_dir().join(format!("megaprompt-git-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let repo = Repository::init(&dir).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        commit(&repo, "a.txt", "First commit");
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("base", &first, false).unwrap();
+        repo.find_branch("main", git2::BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("base"))
+            .unwrap();
+        repo.remote("origin", "git@github.com:zmbush/megaprompt.git")
+            .unwrap();
+
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        commit(&repo, "b.txt", "Add b");
+        fs::write(dir.join("a.txt"), "changed").unwrap();
+        fs::write(dir.join("c.txt"), "c").unwrap();
+
+        let mut lines = PromptLines::new();
+        let context = PromptContext::new(ShellType::Fish, dir.clone());
+        GitPlugin::default().run(&context, &mut lines);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_snapshot!(lines
+            .to_json_pretty()
+            .replace(&link::file_url(&dir), "file://[dir]"));
+    }
+
+    #[test]
+    fn hostile_names_are_escaped() {
+        let dir = env::temp_dir().join(format!("megaprompt-git-hostile-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let repo = Repository::init(&dir).unwrap();
+        repo.set_head("refs/heads/x$(reboot)%n`id`").unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        commit(&repo, "a.txt", "First commit");
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("base", &first, false).unwrap();
+        repo.find_branch("x$(reboot)%n`id`", git2::BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("base"))
+            .unwrap();
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        commit(&repo, "b.txt", "Add $(id) %F");
+        fs::write(dir.join("$(id)\\u%F.txt"), "c").unwrap();
+
+        let drawn = |shell: ShellType| {
+            let mut lines = PromptLines::new();
+            GitPlugin::default().run(&PromptContext::new(shell, dir.clone()), &mut lines);
+            lines
+                .iter()
+                .flat_map(|l| l.parts.iter())
+                .map(|b| b.to_string())
+                .collect::<String>()
+        };
+        let bash = drawn(ShellType::Bash);
+        let zsh = drawn(ShellType::Zsh);
+        let fish = drawn(ShellType::Fish);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(bash.contains("x\\\\$(reboot)%n\\\\`id\\\\`"));
+        assert!(bash.contains("\\\\$(id)\\\\\\\\u%F.txt"));
+        assert!(bash.contains("Add \\\\$(id) %F"));
+        // zsh only expands `$` and backticks with prompt_subst, which the init
+        // script turns on for a `PROMPT` that is never substituted twice
+        assert!(zsh.contains("x$(reboot)%%n`id`"));
+        assert!(zsh.contains("$(id)\\u%%F.txt"));
+        assert!(zsh.contains("Add $(id) %%F"));
+        // Only the branch and its upstream are colored
+        assert!(fish.contains("\x1B[36mx$(reboot)%n`id`\x1B[0m -> \x1B[35mbase\x1B[0m"));
+    }
+
+    #[test]
+    fn finds_web_pages_of_remotes() {
+        let github = Some("https://github.com/zmbush/megaprompt".to_owned());
+        assert_eq!(web_url("git@github.com:zmbush/megaprompt.git"), github);
+        assert_eq!(web_url("https://github.com/zmbush/megaprompt.git"), github);
+        assert_eq!(web_url("ssh://git@github.com:22/zmbush/megaprompt"), github);
+        assert_eq!(
+            web_url("https://me@git.example.com:8443/team/repo/"),
+            Some("https://git.example.com:8443/team/repo".to_owned())
+        );
+        assert_eq!(web_url("/srv/git/repo.git"), None);
+        assert_eq!(web_url("file:///srv/git/repo.git"), None);
+    }
+}
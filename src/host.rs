@@ -0,0 +1,134 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines};
+use std::path::Path;
+
+/// Shows `user@host` next to the working directory when it isn't obvious:
+/// over SSH, inside a container, or as another user than the one logged in
+#[derive(Default)]
+pub struct HostPlugin {
+    options: HostOptions,
+}
+
+/// Options of the `host` plugin
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostOptions {
+    /// Shows `user@host` everywhere
+    always: bool,
+}
+
+impl HostPlugin {
+    pub fn with_options(options: HostOptions) -> HostPlugin {
+        HostPlugin { options }
+    }
+}
+
+/// Returns whether the shell runs inside a docker, podman or systemd
+/// container
+fn in_container(context: &PromptContext) -> bool {
+    context.var("container").is_some()
+        || Path::new("/.dockerenv").exists()
+        || Path::new("/run/.containerenv").exists()
+}
+
+fn is_interesting(context: &PromptContext, in_container: bool) -> bool {
+    let over_ssh = ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+        .iter()
+        .any(|v| context.var(v).is_some());
+    let login = context.var("SUDO_USER").or(context.login.as_deref());
+    let switched = match (login, context.user.as_deref()) {
+        (Some(login), Some(user)) => login != user,
+        _ => false,
+    };
+
+    context.uid == Some(0) || over_ssh || in_container || switched
+}
+
+impl PromptBufferPlugin for HostPlugin {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        if !self.options.always && !is_interesting(context, in_container(context)) {
+            return;
+        }
+
+        let shell = context.shell;
+        let style = lines.style(if context.uid == Some(0) {
+            "prompt.host.root"
+        } else {
+            "prompt.host"
+        });
+        let (text, expanded) = match context.user {
+            Some(ref user) => (
+                format!("{}@{}", shell.escape(user), shell.hostname()),
+                format!("{}@{}", user, shell.expanded_hostname()),
+            ),
+            None => (shell.hostname(), shell.expanded_hostname()),
+        };
+        lines.push_first(
+            shell
+                .new_line()
+                .raw_block(text, style)
+                .expands_to(expanded)
+                .build(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prompt_buffer::ShellType;
+    use std::path::PathBuf;
+
+    fn context(vars: &[(&str, &str)]) -> PromptContext {
+        let mut context = PromptContext::new(ShellType::Bash, PathBuf::from("/"));
+        context.user = Some("me".to_owned());
+        context.login = Some("me".to_owned());
+        context.uid = Some(1000);
+        for &(k, v) in vars {
+            context.env.insert(k.to_owned(), v.to_owned());
+        }
+        context
+    }
+
+    #[test]
+    fn only_shows_up_when_it_matters() {
+        assert!(!is_interesting(&context(&[]), false));
+        assert!(is_interesting(&context(&[]), true));
+        assert!(is_interesting(
+            &context(&[("SSH_CONNECTION", "10.0.0.1 22 10.0.0.2 22")]),
+            false
+        ));
+        assert!(is_interesting(&context(&[("SUDO_USER", "you")]), false));
+
+        let mut su = context(&[]);
+        su.login = Some("you".to_owned());
+        assert!(is_interesting(&su, false));
+
+        let mut root = context(&[]);
+        root.uid = Some(0);
+        assert!(is_interesting(&root, false));
+    }
+
+    #[test]
+    fn joins_the_first_line() {
+        let mut lines = PromptLines::new();
+        lines.push(ShellType::Bash.new_line().block("~").build());
+
+        let mut root = context(&[]);
+        root.uid = Some(0);
+        root.user = Some("$(reboot)".to_owned());
+        HostPlugin::default().run(&root, &mut lines);
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].parts.len(), 2);
+        assert!(lines[0].parts[1].plain_text().starts_with("$(reboot)@"));
+        assert!(lines[0].parts[1].to_string().contains("\\\\$(reboot)@\\H"));
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use prompt_buffer::ShellType;
+use std::path::Path;
+
+fn quote(shell: ShellType, s: &str) -> String {
+    match shell {
+        ShellType::Fish => format!("'{}'", s.replace('\\', r#"\\"#).replace('\'', r#"\'"#)),
+        ShellType::Nushell => {
+            // A raw string ends at a quote followed by as many #s as it
+            // started with
+            let hashes = (1..)
+                .map(|n| "#".repeat(n))
+                .find(|h| !s.contains(&format!("'{}", h)))
+                .unwrap_or_default();
+            format!("r{}'{}'{}", hashes, s, hashes)
+        }
+        _ => format!("'{}'", s.replace('\'', r#"'\''"#)),
+    }
+}
+
+pub fn script(shell: ShellType, exe: &Path) -> String {
+    let template = match shell {
+        ShellType::Bash => include_str!("init/bash.sh"),
+        ShellType::Zsh => include_str!("init/zsh.zsh"),
+        ShellType::Fish => include_str!("init/fish.fish"),
+        ShellType::Nushell => include_str!("init/nushell.nu"),
+        // tcsh has no multi-line eval, so its script is a single line of
+        // commands:
+        //
+        //     eval "`megaprompt init tcsh`"
+        ShellType::Tcsh => include_str!("init/tcsh.tcsh"),
+        ShellType::Ksh => include_str!("init/ksh.ksh"),
+    };
+
+    template.replace("@MEGAPROMPT@", &quote(shell, &format!("{}", exe.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::script;
+    use prompt_buffer::ShellType;
+    use std::path::Path;
+
+    fn snapshot(shell: ShellType) -> String {
+        script(shell, Path::new("/usr/local/bin/megaprompt"))
+    }
+
+    #[test]
+    fn bash() {
+        assert_snapshot!(snapshot(ShellType::Bash));
+    }
+
+    #[test]
+    fn zsh() {
+        assert_snapshot!(snapshot(ShellType::Zsh));
+    }
+
+    #[test]
+    fn fish() {
+        assert_snapshot!(snapshot(ShellType::Fish));
+    }
+
+    #[test]
+    fn nushell() {
+        assert_snapshot!(snapshot(ShellType::Nushell));
+    }
+
+    #[test]
+    fn tcsh() {
+        assert_snapshot!(snapshot(ShellType::Tcsh));
+    }
+
+    #[test]
+    fn ksh() {
+        assert_snapshot!(snapshot(ShellType::Ksh));
+    }
+
+    #[test]
+    fn zsh_prompt_is_not_substituted_twice() {
+        let s = snapshot(ShellType::Zsh);
+        assert!(s.contains("setopt prompt_subst"));
+        assert!(s.contains("PROMPT='${__megaprompt_left}'"));
+        assert!(!s.contains("PROMPT=$"));
+    }
+
+    #[test]
+    fn quotes_the_executable() {
+        let s = script(ShellType::Bash, Path::new("/home/o'brien/bin/megaprompt"));
+        assert!(s.contains(r#"PS1=$('/home/o'\''brien/bin/megaprompt' --bash"#));
+
+        let s = script(ShellType::Nushell, Path::new("/opt/`it's'#/megaprompt"));
+        assert!(s.contains(r###"let megaprompt = r##'/opt/`it's'#/megaprompt'##"###));
+    }
+}
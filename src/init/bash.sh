@@ -0,0 +1,41 @@
+# megaprompt integration for bash
+#
+# Add this to ~/.bashrc:
+#
+#     eval "$(megaprompt init bash)"
+
+command -v megapromptd >/dev/null 2>&1 && megapromptd start >/dev/null 2>&1
+
+__megaprompt_preexec() {
+    # DEBUG fires before every simple command, only the first one after a
+    # prompt starts the clock
+    if [ -n "$__megaprompt_ready" ]; then
+        unset __megaprompt_ready
+        # EPOCHREALTIME is new in bash 5, older ones time in whole seconds
+        __megaprompt_start=${EPOCHREALTIME:-$(date +%s)}
+    fi
+}
+
+__megaprompt_precmd() {
+    local exit_status=$?
+    # EPOCHREALTIME has a decimal comma in some locales
+    local start=${__megaprompt_start/,/.}
+    unset __megaprompt_start
+    local -a job_pids
+    job_pids=($(jobs -p))
+
+    PS1=$(@MEGAPROMPT@ --bash --pid $$ --status "$exit_status" --jobs ${#job_pids[@]} ${start:+--command-start "$start"})
+    __megaprompt_ready=1
+}
+
+# Keep the DEBUG trap that was set before, such as by other preexec hooks.
+# trap -p prints `trap -- 'COMMAND' DEBUG`, with COMMAND quoted for eval
+__megaprompt_debug=$(trap -p DEBUG)
+__megaprompt_debug=${__megaprompt_debug#trap -- }
+eval "__megaprompt_debug=${__megaprompt_debug% DEBUG}"
+case $__megaprompt_debug in
+    *__megaprompt_preexec*) ;;
+    *) trap "${__megaprompt_debug:+$__megaprompt_debug; }__megaprompt_preexec" DEBUG ;;
+esac
+unset __megaprompt_debug
+PROMPT_COMMAND=__megaprompt_precmd
@@ -0,0 +1,20 @@
+# megaprompt integration for fish
+#
+# Add this to ~/.config/fish/config.fish:
+#
+#     megaprompt init fish | source
+
+if command -q megapromptd
+    megapromptd start >/dev/null 2>&1
+end
+
+function fish_prompt
+    set -l exit_status $status
+    set -l out (string split \x1f -- (@MEGAPROMPT@ --fish --rprompt --pid $fish_pid --status $exit_status --duration $CMD_DURATION --jobs (count (jobs -p)) | string collect))
+    set -g __megaprompt_right $out[2]
+    printf '%s' $out[1]
+end
+
+function fish_right_prompt
+    printf '%s' $__megaprompt_right
+end
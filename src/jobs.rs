@@ -0,0 +1,63 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines};
+
+/// Shows how many jobs the shell has in the background
+#[derive(Default)]
+pub struct JobsPlugin;
+
+impl JobsPlugin {
+    pub fn new() -> JobsPlugin {
+        JobsPlugin
+    }
+}
+
+impl PromptBufferPlugin for JobsPlugin {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        match context.state.jobs {
+            None | Some(0) => {}
+            Some(jobs) => {
+                let style = lines.style("jobs");
+                lines.push(
+                    context
+                        .shell
+                        .new_line()
+                        .styled_block(format!("Jobs: {}", jobs), style)
+                        .build(),
+                );
+            }
+        }
+    }
+
+    fn uses_state(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JobsPlugin;
+    use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines, ShellType};
+    use std::path::PathBuf;
+
+    fn drawn(jobs: Option<u32>) -> Vec<String> {
+        let mut context = PromptContext::new(ShellType::Bash, PathBuf::from("/"));
+        context.state.jobs = jobs;
+        let mut lines = PromptLines::new();
+        JobsPlugin::new().run(&context, &mut lines);
+        lines.iter().map(|l| l.parts[0].plain_text()).collect()
+    }
+
+    #[test]
+    fn counts_jobs() {
+        assert!(drawn(None).is_empty());
+        assert!(drawn(Some(0)).is_empty());
+        assert_eq!(drawn(Some(2)), ["Jobs: 2"]);
+    }
+}
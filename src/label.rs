@@ -0,0 +1,48 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use prompt_buffer::{PromptBufferPlugin, PromptContext, PromptLines};
+
+/// Options of the `label` plugin
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LabelOptions {
+    /// Shown next to the working directory, usually set by a
+    /// `.megaprompt.toml`
+    text: String,
+}
+
+/// Shows a fixed label, such as the name of a project, next to the working
+/// directory
+#[derive(Default)]
+pub struct LabelPlugin {
+    options: LabelOptions,
+}
+
+impl LabelPlugin {
+    pub fn with_options(options: LabelOptions) -> LabelPlugin {
+        LabelPlugin { options }
+    }
+}
+
+impl PromptBufferPlugin for LabelPlugin {
+    fn run(&mut self, context: &PromptContext, lines: &mut PromptLines) {
+        if self.options.text.is_empty() {
+            return;
+        }
+
+        let style = lines.style("label");
+        lines.push_first(
+            context
+                .shell
+                .new_line()
+                .styled_block(&self.options.text, style)
+                .build(),
+        );
+    }
+}
@@ -22,44 +22,61 @@
 extern crate chan;
 extern crate clap;
 extern crate git2;
+extern crate libc;
+#[cfg(test)]
+#[macro_use]
+extern crate insta;
 extern crate log4rs;
 #[macro_use]
 extern crate log;
 extern crate prompt_buffer;
-extern crate term;
+#[macro_use]
+extern crate serde;
+extern crate serde_json;
 extern crate time;
+extern crate toml;
 
-use prompt_buffer::{PromptBuffer, PromptThread, ShellType};
+use config::Config;
+use prompt_buffer::{
+    Format, LineStyle, PluginSpeed, PromptBuffer, PromptContext, PromptOutput, PromptThread,
+    RenderOptions, Renderer, ShellState, ShellType,
+};
+use protocol::Request;
 
 use std::collections::HashMap;
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 
 use log4rs::append::file::FileAppender;
-use log4rs::config;
+use log4rs::config as log_config;
 use log4rs::encode::pattern::PatternEncoder;
 use time::Duration;
 
 use chan::Receiver;
-use clap::{ArgGroup, Parser};
+use clap::{ArgGroup, Parser, Subcommand};
 use std::env;
 use std::net::Shutdown;
-use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{self, Command};
+use std::str::FromStr;
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+mod config;
 mod due_date;
+mod duration;
+mod exit_status;
+mod external;
 mod git;
-
-fn get_prompt(shell: ShellType) -> PromptBuffer {
-    let mut buf = PromptBuffer::new(shell);
-    buf.add_plugin(due_date::DueDatePlugin::new());
-    buf.add_plugin(git::GitPlugin::new());
-
-    buf
-}
+mod host;
+mod init;
+mod jobs;
+mod label;
+mod period;
+mod protocol;
+mod trust;
 
 fn exe_changed() -> i64 {
     match env::current_exe() {
@@ -84,13 +101,38 @@ macro_rules! sock_try {
 enum RunMode {
     Daemon,
     Main,
+    Transient,
     Test,
 }
 
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Print the script that sets up megaprompt for a shell
+    Init {
+        /// bash, zsh, fish, nushell, tcsh or ksh
+        shell: ShellType,
+    },
+
+    /// Use the .megaprompt.toml of a directory, as it is now
+    Trust {
+        /// Directory of the .megaprompt.toml, the current one by default
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Stop using it instead
+        #[arg(long)]
+        revoke: bool,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about=None)]
-#[command(group(ArgGroup::new("mode").required(true).args(["daemon", "bash", "zsh"])))]
+#[command(group(ArgGroup::new("mode").required(true).args(["daemon", "bash", "zsh", "fish", "nushell", "tcsh", "ksh"])))]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Run the daemon
     #[arg(short, long)]
     daemon: bool,
@@ -102,46 +144,248 @@ struct Args {
     // Get output for zsh
     #[arg(short, long)]
     zsh: bool,
+
+    // Get output for fish
+    #[arg(short, long)]
+    fish: bool,
+
+    // Get output for nushell
+    #[arg(short, long)]
+    nushell: bool,
+
+    // Get output for tcsh/csh
+    #[arg(short, long)]
+    tcsh: bool,
+
+    // Get output for ksh/mksh
+    #[arg(short, long)]
+    ksh: bool,
+
+    /// Also print the right prompt, separated from the left one by 0x1F
+    #[arg(long)]
+    rprompt: bool,
+
+    /// Print the collapsed one line prompt that replaces a submitted prompt
+    #[arg(long, conflicts_with = "daemon")]
+    transient: bool,
+
+    /// How to draw the prompt: boxes or powerline
+    #[arg(long, conflicts_with = "daemon")]
+    renderer: Option<Renderer>,
+
+    /// Characters to draw the lines with: light, heavy, double, rounded or
+    /// ascii. Defaults to ascii unless the locale is UTF-8
+    #[arg(long, conflicts_with = "daemon")]
+    lines: Option<LineStyle>,
+
+    /// Width of the terminal. Defaults to $COLUMNS, or asking the terminal
+    #[arg(long, conflicts_with = "daemon")]
+    columns: Option<usize>,
+
+    /// Theme to draw the prompt with: default, solarized, mono, or the path
+    /// of a theme file. Defaults to $MEGAPROMPT_THEME
+    #[arg(long, conflicts_with = "daemon")]
+    theme: Option<String>,
+
+    /// Print the prompt, or the data in it as JSON (prompt or json)
+    #[arg(long, conflicts_with = "daemon")]
+    format: Option<Format>,
+
+    /// Template of the terminal title, such as `[{repo}:{branch} — ]{dir}`.
+    /// Defaults to $MEGAPROMPT_TITLE
+    #[arg(long, conflicts_with_all = ["daemon", "no_title"])]
+    title: Option<String>,
+
+    /// Leave the terminal title alone
+    #[arg(long, conflicts_with = "daemon")]
+    no_title: bool,
+
+    // What the shell says about the last command is parsed by `state_arg`,
+    // so a malformed value only loses its own part of the prompt
+    /// Exit status of the last command
+    #[arg(long)]
+    status: Option<String>,
+
+    /// Run time of the last command in milliseconds
+    #[arg(long, conflicts_with = "command_start")]
+    duration: Option<String>,
+
+    /// Unix time in seconds at which the last command was started
+    #[arg(long)]
+    command_start: Option<String>,
+
+    /// Number of background and suspended jobs
+    #[arg(long)]
+    jobs: Option<String>,
+
+    /// Process id of the shell
+    #[arg(long)]
+    pid: Option<u32>,
 }
 
 #[allow(dead_code)]
 fn main() {
     let args = Args::parse();
+    match args.command {
+        Some(Commands::Init { shell }) => {
+            let exe = env::current_exe().unwrap_or_else(|_| PathBuf::from("megaprompt"));
+            print!("{}", init::script(shell, &exe));
+            return;
+        }
+        Some(Commands::Trust { ref dir, revoke }) => {
+            let result = if revoke {
+                trust::revoke(dir).map(|f| format!("No longer using {}", f.display()))
+            } else {
+                trust::trust(dir).map(|f| format!("Using {}", f.display()))
+            };
+            match result {
+                Ok(message) => println!("{}", message),
+                Err(e) => {
+                    eprintln!("megaprompt: {}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        None => {}
+    }
+
     let shell = if args.bash {
         ShellType::Bash
+    } else if args.fish {
+        ShellType::Fish
+    } else if args.nushell {
+        ShellType::Nushell
+    } else if args.tcsh {
+        ShellType::Tcsh
+    } else if args.ksh {
+        ShellType::Ksh
     } else {
         ShellType::Zsh
     };
+    let mut options = RenderOptions::from_env();
+    if let Some(renderer) = args.renderer {
+        options.renderer = renderer;
+    }
+    if let Some(lines) = args.lines {
+        options.lines = lines;
+    }
+    if args.columns.is_some() {
+        options.columns = args.columns;
+    }
+    if args.theme.is_some() {
+        options.theme = args.theme;
+    }
+    if let Some(format) = args.format {
+        options.format = format;
+    }
+    if args.title.is_some() {
+        options.title = args.title;
+    }
+    if args.no_title {
+        options.title = None;
+    }
+
+    let command_start = state_arg(args.command_start);
+    let mut context = PromptContext::from_env(shell);
+    context.pid = args.pid;
+    context.state = ShellState {
+        exit_status: state_arg(args.status),
+        duration: state_arg(args.duration)
+            .map(::std::time::Duration::from_millis)
+            .or_else(|| command_start.and_then(elapsed_since)),
+        jobs: state_arg(args.jobs),
+    };
+
     run(
         if args.daemon {
             RunMode::Daemon
+        } else if args.transient {
+            RunMode::Transient
         } else {
             RunMode::Main
         },
-        shell,
+        args.rprompt,
+        options,
+        context,
     )
 }
 
+/// Parses an argument about the last command, which is left out if it is
+/// malformed
+///
+/// Shells format times in the locale's way, with a decimal comma in many
+/// locales, so a comma is read as a decimal point
+fn state_arg<T: FromStr>(arg: Option<String>) -> Option<T> {
+    arg.and_then(|a| a.trim().replace(',', ".").parse().ok())
+}
+
+/// Returns how long ago `start`, in seconds since the epoch, was
+fn elapsed_since(start: f64) -> Option<::std::time::Duration> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    let elapsed = now.as_secs_f64() - start;
+    if elapsed.is_finite() && elapsed >= 0.0 {
+        Some(::std::time::Duration::from_secs_f64(elapsed))
+    } else {
+        None
+    }
+}
+
+/// Returns the directory of the daemon's socket: `$XDG_RUNTIME_DIR/megaprompt`,
+/// or a directory of the user's in `/tmp`
+fn socket_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("megaprompt"),
+        None => env::temp_dir().join(format!("megaprompt-{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// Returns whether `dir` belongs to the user and nobody else can get in.
+/// Clients send their environment over the socket, secrets and all
+fn is_private(dir: &Path) -> bool {
+    fs::symlink_metadata(dir)
+        .map(|m| m.is_dir() && m.uid() == unsafe { libc::getuid() } && m.mode() & 0o077 == 0)
+        .unwrap_or(false)
+}
+
+/// Prompts are drawn by a thread per directory, shell and output format
+type ThreadKey = (PathBuf, ShellType, Format);
+
 fn do_daemon(socket_path: &Path) {
     let main_log = FileAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{h({f:>30.30}: {m}{n})}")))
         .build("/var/log/megaprompt/current.out")
         .expect("Unable to create file appender");
 
-    let config = config::Config::builder()
-        .appender(config::Appender::builder().build("main", Box::new(main_log)))
+    let log = log_config::Config::builder()
+        .appender(log_config::Appender::builder().build("main", Box::new(main_log)))
         .build(
-            config::Root::builder()
+            log_config::Root::builder()
                 .appender("main")
                 .build(log::LevelFilter::Trace),
         )
         .expect("Unable to create logger config");
 
-    log4rs::init_config(config).expect("Unable to init logger");
+    log4rs::init_config(log).expect("Unable to init logger");
 
-    let last_modified = exe_changed();
-    let mut threads: HashMap<(PathBuf, ShellType), PromptThread> = HashMap::new();
+    let mut config = Config::load_or_default();
+    let mut config_modified = Config::modified();
 
+    let last_modified = exe_changed();
+    let mut threads: HashMap<ThreadKey, PromptThread> = HashMap::new();
+    // The config each thread's plugins were built from
+    let mut configs: HashMap<ThreadKey, Config> = HashMap::new();
+
+    if let Some(dir) = socket_path.parent() {
+        let _ = fs::DirBuilder::new().mode(0o700).create(dir);
+        if !is_private(dir) {
+            error!(
+                "{} is not a private directory. Not listening there",
+                dir.display()
+            );
+            return;
+        }
+    }
     if socket_path.exists() {
         fs::remove_file(socket_path).expect("Unable to remove socket file");
     }
@@ -159,27 +403,30 @@ fn do_daemon(socket_path: &Path) {
             Err(_) => continue,
         };
 
-        let mut output = String::new();
-        let _ = sock_try!(c.read_to_string(&mut output));
-        let (output, shell) = if output.starts_with("!2 ") {
-            let parts = output.split(' ').collect::<Vec<_>>();
-            let output = PathBuf::from(&parts[1]);
-            let shell = match parts[2] {
-                "Bash" => ShellType::Bash,
-                "Zsh" => ShellType::Zsh,
-                _ => ShellType::Bash,
-            };
-            (output, shell)
-        } else {
-            (PathBuf::from(&output), ShellType::Bash)
-        };
+        let modified = Config::modified();
+        if modified != config_modified {
+            info!("Config changed. Reloading it");
+            config_modified = modified;
+            config = Config::load_or_default();
+        }
+
+        let mut input = String::new();
+        let _ = sock_try!(c.read_to_string(&mut input));
+        let Request {
+            mut options,
+            context,
+        } = Request::decode(&input);
+        let dir_config = config.for_dir(&context.path);
+        dir_config.apply(&mut options);
+        let output = context.path.clone();
+        let shell = context.shell;
         info!(
             "Preparing to respond to for {} [{:?}]",
             output.display(),
             shell
         );
 
-        let keys: Vec<(PathBuf, ShellType)> = threads.keys().cloned().collect();
+        let keys: Vec<ThreadKey> = threads.keys().cloned().collect();
         for entry in &keys {
             if !threads
                 .get_mut(entry)
@@ -188,32 +435,50 @@ fn do_daemon(socket_path: &Path) {
             {
                 info!("- Remove thread {}", entry.0.display());
                 let _ = threads.remove(entry);
+                let _ = configs.remove(entry);
             }
         }
 
-        if let std::collections::hash_map::Entry::Vacant(e) = threads.entry((output.clone(), shell))
-        {
+        let format = options.format;
+        let key = (output.clone(), shell, format);
+        if let std::collections::hash_map::Entry::Vacant(e) = threads.entry(key.clone()) {
             info!("+ Add thread {}", output.display());
-            let t = sock_try!(PromptThread::new(output.clone(), &|| get_prompt(shell)));
+            let t = sock_try!(PromptThread::new(output.clone(), options.clone(), &|| {
+                dir_config.prompt(shell)
+            }));
             e.insert(t);
+            configs.insert(key.clone(), dir_config.clone());
         }
 
-        for (path, shell) in threads.keys() {
-            info!("* Active thread {} [{:?}]", path.display(), shell);
+        for (path, shell, format) in threads.keys() {
+            info!(
+                "* Active thread {} [{:?}, {:?}]",
+                path.display(),
+                shell,
+                format
+            );
         }
 
-        let thr = threads
-            .get_mut(&(output, shell))
-            .expect("Thread not present");
+        let thr = threads.get_mut(&key).expect("Thread not present");
+        if configs.get(&key) != Some(&dir_config) {
+            info!("Config changed. Rebuilding plugins");
+            thr.replace(&|| dir_config.prompt(shell));
+            configs.insert(key, dir_config.clone());
+        }
 
         info!("Getting response from thread");
-        sock_try!(write!(c, "{}", sock_try!(thr.get(&|| get_prompt(shell)))));
+        let mut response = sock_try!(thr.get(options, context, &|| dir_config.prompt(shell)));
+
+        let restart = last_modified != exe_changed();
+        if restart && format == Format::Prompt {
+            response.left.push_str("♻  ");
+        }
+        sock_try!(write!(c, "{}", response.encode()));
 
         info!("");
 
-        if last_modified != exe_changed() {
+        if restart {
             warn!("Found newer version of myself. Quitting.");
-            sock_try!(write!(c, "♻  "));
             return;
         }
     }
@@ -245,7 +510,7 @@ fn read_with_timeout(mut stream: UnixStream, dur: Duration) -> Result<String, St
 
     let timeout = oneshot_timer(dur);
 
-    #[allow(unused_mut)]
+    #[allow(unused_mut, clippy::needless_return)]
     {
         chan_select! {
             rx.recv() ->resp => return Ok(resp.expect("There is no response!")),
@@ -254,51 +519,121 @@ fn read_with_timeout(mut stream: UnixStream, dur: Duration) -> Result<String, St
     }
 }
 
-fn do_main(socket_path: &Path, shell: ShellType) {
+fn print_output(output: &PromptOutput, rprompt: bool) {
+    if rprompt {
+        print!("{}", output.encode());
+    } else {
+        print!("{}", output.left);
+    }
+}
+
+fn fallback_output(
+    mut options: RenderOptions,
+    context: PromptContext,
+    speed: PluginSpeed,
+) -> PromptOutput {
+    let config = Config::load_or_default().for_dir(&context.path);
+    config.apply(&mut options);
+    let mut buf = config.prompt(context.shell);
+    buf.set_render_options(options);
+    buf.set_context(context);
+    buf.convert_to_output(speed)
+}
+
+fn do_main(socket_path: &Path, rprompt: bool, options: RenderOptions, context: PromptContext) {
     let _ = Command::new("megapromptd").arg("start").output();
 
-    let mut stream = match UnixStream::connect(socket_path) {
+    let private = socket_path.parent().map(is_private).unwrap_or(false);
+    let connection = if private {
+        UnixStream::connect(socket_path)
+    } else {
+        Err(io::Error::from(io::ErrorKind::PermissionDenied))
+    };
+    let mut stream = match connection {
         Err(_) => {
-            println!("Can't connect");
-            get_prompt(shell).print();
+            // Keep JSON output parseable
+            if options.format == Format::Prompt {
+                println!("Can't connect");
+            }
+            print_output(
+                &fallback_output(options, context, PluginSpeed::Slow),
+                rprompt,
+            );
             return;
         }
         Ok(stream) => stream,
     };
 
-    write!(
-        &mut stream,
-        "!2 {} {:?}",
-        env::current_dir()
-            .expect("There is no current dir")
-            .display(),
-        shell
-    )
-    .expect("Unable to print current directory");
+    let request = Request {
+        options: options.clone(),
+        context: context.clone(),
+    };
+    write!(&mut stream, "{}", request.encode()).expect("Unable to print current directory");
     stream
         .shutdown(Shutdown::Write)
         .expect("Cannot shutdown stream");
 
     match read_with_timeout(stream, Duration::milliseconds(100)) {
-        Ok(s) => println!("{}", s),
+        Ok(s) => print_output(&PromptOutput::decode(&s), rprompt),
         Err(_) => {
-            println!("Response too slow");
-            get_prompt(shell).print_fast();
+            if options.format == Format::Prompt {
+                println!("Response too slow");
+            }
+            print_output(
+                &fallback_output(options, context, PluginSpeed::Fast),
+                rprompt,
+            );
         }
     }
 }
 
-fn run(mode: RunMode, shell: ShellType) {
-    let socket_path = Path::new("/tmp/megaprompt-socket");
+fn run(mode: RunMode, rprompt: bool, options: RenderOptions, context: PromptContext) {
+    let socket_path = socket_dir().join("socket");
 
     match mode {
-        RunMode::Daemon => do_daemon(socket_path),
-        RunMode::Main => do_main(socket_path, shell),
+        RunMode::Daemon => do_daemon(&socket_path),
+        RunMode::Main => do_main(&socket_path, rprompt, options, context),
+        RunMode::Transient => {
+            let mut buf = PromptBuffer::new(context.shell);
+            buf.set_render_options(options);
+            buf.set_context(context);
+            print!("{}", buf.convert_to_compact_string());
+        }
         RunMode::Test => {}
     }
 }
 
 #[test]
 fn test_main_does_not_error() {
-    run(RunMode::Test, ShellType::Bash);
+    run(
+        RunMode::Test,
+        false,
+        RenderOptions::default(),
+        PromptContext::from_env(ShellType::Bash),
+    );
+}
+
+#[test]
+fn socket_dir_is_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = env::temp_dir().join(format!("megaprompt-private-{}", process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&dir).unwrap();
+    assert!(is_private(&dir));
+
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(!is_private(&dir));
+    fs::remove_dir(&dir).unwrap();
+    assert!(!is_private(&dir));
+}
+
+#[test]
+fn malformed_state_is_left_out() {
+    assert_eq!(
+        state_arg::<f64>(Some("1700000000,123".to_owned())),
+        Some(1700000000.123)
+    );
+    assert_eq!(state_arg::<i32>(Some("130".to_owned())), Some(130));
+    assert_eq!(state_arg::<u32>(Some("".to_owned())), None);
+    assert_eq!(state_arg::<u64>(Some("soon".to_owned())), None);
 }
@@ -0,0 +1,95 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Spells out lengths of time, like `2 hours 5 minutes`
+
+struct TimePeriod {
+    singular: String,
+    plural: String,
+}
+
+trait ToTimePeriod {
+    fn as_period(&self) -> TimePeriod;
+}
+
+impl ToTimePeriod for str {
+    fn as_period(&self) -> TimePeriod {
+        let mut p = self.to_owned();
+        p.push('s');
+
+        (self, p.as_ref()).as_period()
+    }
+}
+
+impl<'s> ToTimePeriod for (&'s str, &'s str) {
+    fn as_period(&self) -> TimePeriod {
+        let (s, p) = *self;
+        TimePeriod {
+            singular: s.to_owned(),
+            plural: p.to_owned(),
+        }
+    }
+}
+
+/// Describes `seconds` with at most `accuracy` units, largest first
+pub fn describe(seconds: f32, accuracy: u8) -> String {
+    let mut seconds = seconds;
+
+    let ups: [f32; 9] = [10.0, 10.0, 10.0, 365.0 / 30.0, 30.0, 24.0, 60.0, 60.0, 1.0];
+    let time_periods = [
+        ("millenium", "millenia").as_period(),
+        ("century", "centuries").as_period(),
+        "decade".as_period(),
+        "year".as_period(),
+        "month".as_period(),
+        "day".as_period(),
+        "hour".as_period(),
+        "minute".as_period(),
+        "second".as_period(),
+    ];
+
+    let times = (0..ups.len()).map(|i| ups[i..].iter().fold(1.0, |a, &b| a * b));
+
+    let mut count = 0u8;
+    let mut phrase = String::new();
+
+    for (amount, name) in times.zip(time_periods.iter()) {
+        if seconds > amount {
+            count += 1;
+            let rem = seconds % amount;
+            let amt = seconds / amount - (rem / amount);
+            seconds = rem;
+            let name = if amt > 1.0 {
+                &name.plural
+            } else {
+                &name.singular
+            };
+            phrase = format!("{}{} {} ", phrase, amt.round() as i32, name);
+        }
+
+        if count >= accuracy {
+            break;
+        }
+    }
+
+    phrase.trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+
+    #[test]
+    fn describes_periods() {
+        assert_eq!(describe(5.5, 2), "5 seconds");
+        assert_eq!(describe(65.0, 2), "1 minute 5 seconds");
+        assert_eq!(describe(2.0 * 3600.0 + 61.0, 2), "2 hours 1 minute");
+        assert_eq!(describe(3.0 * 86400.0 + 3600.0 + 5.0, 2), "3 days 1 hour");
+        assert_eq!(describe(0.5, 2), "");
+    }
+}
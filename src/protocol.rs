@@ -0,0 +1,184 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use prompt_buffer::{PromptContext, RenderOptions, ShellType};
+use serde_json;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Requests sent from the client to the daemon. Three versions are understood:
+//
+//     /some/path               (v1, bash only)
+//     !2 /some/path Zsh        (v2)
+//     !3\npath=/some/path\nshell=Zsh\ncolors=256\nrenderer=boxes\nlines=light\nformat=prompt\ncolumns=80\ntheme=solarized\ntitle={dir}\nstatus=130\nduration=1500\njobs=2\nuser=me\nlogin=me\nuid=1000\npid=4242\nenv={"HOME":"/home/me"}\n
+//
+// v3 is a `key=value` line per field. Unknown keys are ignored so fields
+// can be added without breaking a daemon that is still running. The
+// environment is a JSON object, which keeps values with newlines on one line.
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub options: RenderOptions,
+    pub context: PromptContext,
+}
+
+impl Request {
+    pub fn encode(&self) -> String {
+        let context = &self.context;
+        let mut s = format!(
+            "!3\npath={}\nshell={:?}\ncolors={}\nrenderer={}\nlines={}\nformat={}\n",
+            context.path.display(),
+            context.shell,
+            self.options.colors,
+            self.options.renderer,
+            self.options.lines,
+            self.options.format
+        );
+        if let Some(columns) = self.options.columns {
+            s.push_str(&format!("columns={}\n", columns));
+        }
+        if let Some(ref theme) = self.options.theme {
+            s.push_str(&format!("theme={}\n", theme));
+        }
+        if let Some(ref title) = self.options.title {
+            s.push_str(&format!("title={}\n", title));
+        }
+        if let Some(status) = context.state.exit_status {
+            s.push_str(&format!("status={}\n", status));
+        }
+        if let Some(duration) = context.state.duration {
+            s.push_str(&format!("duration={}\n", duration.as_millis()));
+        }
+        if let Some(jobs) = context.state.jobs {
+            s.push_str(&format!("jobs={}\n", jobs));
+        }
+        if let Some(ref user) = context.user {
+            s.push_str(&format!("user={}\n", user));
+        }
+        if let Some(ref login) = context.login {
+            s.push_str(&format!("login={}\n", login));
+        }
+        if let Some(uid) = context.uid {
+            s.push_str(&format!("uid={}\n", uid));
+        }
+        if let Some(pid) = context.pid {
+            s.push_str(&format!("pid={}\n", pid));
+        }
+        if !context.env.is_empty() {
+            if let Ok(env) = serde_json::to_string(&context.env) {
+                s.push_str(&format!("env={}\n", env));
+            }
+        }
+        s
+    }
+
+    pub fn decode(s: &str) -> Request {
+        let mut request = Request {
+            options: RenderOptions::default(),
+            context: PromptContext::new(ShellType::Bash, PathBuf::from(s)),
+        };
+
+        if s.starts_with("!3\n") {
+            let context = &mut request.context;
+            for line in s.lines().skip(1) {
+                let (key, value) = match line.split_once('=') {
+                    Some(kv) => kv,
+                    None => continue,
+                };
+                match key {
+                    "path" => context.path = PathBuf::from(value),
+                    "shell" => context.shell = value.parse().unwrap_or(ShellType::Bash),
+                    "colors" => {
+                        request.options.colors = value.parse().unwrap_or_default();
+                    }
+                    "renderer" => {
+                        request.options.renderer = value.parse().unwrap_or_default();
+                    }
+                    "lines" => request.options.lines = value.parse().unwrap_or_default(),
+                    "format" => request.options.format = value.parse().unwrap_or_default(),
+                    "columns" => request.options.columns = value.parse().ok(),
+                    "theme" => request.options.theme = Some(value.to_owned()),
+                    "title" => request.options.title = Some(value.to_owned()),
+                    "status" => context.state.exit_status = value.parse().ok(),
+                    "duration" => {
+                        context.state.duration = value.parse().ok().map(Duration::from_millis)
+                    }
+                    "jobs" => context.state.jobs = value.parse().ok(),
+                    "user" => context.user = Some(value.to_owned()),
+                    "login" => context.login = Some(value.to_owned()),
+                    "uid" => context.uid = value.parse().ok(),
+                    "pid" => context.pid = value.parse().ok(),
+                    "env" => context.env = serde_json::from_str(value).unwrap_or_default(),
+                    _ => {}
+                }
+            }
+        } else if s.starts_with("!2 ") {
+            let parts = s.split(' ').collect::<Vec<_>>();
+            request.context.path = PathBuf::from(&parts[1]);
+            request.context.shell = parts
+                .get(2)
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(ShellType::Bash);
+        }
+
+        request
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Request;
+    use prompt_buffer::color::ColorSupport;
+    use prompt_buffer::{
+        Format, LineStyle, PromptContext, RenderOptions, Renderer, ShellState, ShellType,
+    };
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn round_trips() {
+        let mut context = PromptContext::new(ShellType::Fish, PathBuf::from("/home/me/some dir"));
+        context.state = ShellState {
+            exit_status: Some(130),
+            duration: Some(Duration::from_millis(61_500)),
+            jobs: Some(2),
+        };
+        context.user = Some("me".to_owned());
+        context.login = Some("someone".to_owned());
+        context.uid = Some(1000);
+        context.pid = Some(4242);
+        context
+            .env
+            .insert("BASH_FUNC_f%%".to_owned(), "() {\n  echo a=b\n}".to_owned());
+        context.env.insert("HOME".to_owned(), "/home/me".to_owned());
+
+        let request = Request {
+            options: RenderOptions {
+                colors: ColorSupport::TrueColor,
+                renderer: Renderer::Powerline,
+                lines: LineStyle::Rounded,
+                format: Format::Json,
+                columns: Some(120),
+                theme: Some("/home/me/theme=dark.toml".to_owned()),
+                title: Some("[{repo}:{branch} — ]{dir}".to_owned()),
+            },
+            context,
+        };
+        assert_eq!(Request::decode(&request.encode()), request);
+    }
+
+    #[test]
+    fn understands_old_clients() {
+        let v2 = Request::decode("!2 /tmp Zsh");
+        assert_eq!(v2.context.path, PathBuf::from("/tmp"));
+        assert_eq!(v2.context.shell, ShellType::Zsh);
+
+        let v1 = Request::decode("/tmp");
+        assert_eq!(v1.context.path, PathBuf::from("/tmp"));
+        assert_eq!(v1.context.shell, ShellType::Bash);
+    }
+}
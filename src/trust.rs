@@ -0,0 +1,112 @@
+// Copyright 2017 Zachary Bush.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Which `.megaprompt.toml` files are used
+//!
+//! A cloned repository could otherwise change what the prompt runs. Trust is
+//! given to the contents of a file: `~/.config/megaprompt/trusted` has a line
+//! with the git blob id and path of every trusted file, so a file has to be
+//! trusted again after it changes.
+
+use config::{Config, DIR_FILE};
+use git2::{ObjectType, Oid};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn list_path() -> Option<PathBuf> {
+    Config::dir().map(|d| d.join("trusted"))
+}
+
+/// Returns the line of the trust list for `file` with contents `s`
+fn entry(file: &Path, s: &str) -> String {
+    let id = Oid::hash_object(ObjectType::Blob, s.as_bytes())
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+    format!("{} {}", id, file.display())
+}
+
+/// Returns the lines of the trust list
+pub fn trusted() -> Vec<String> {
+    list_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .map(|s| s.lines().map(|l| l.to_owned()).collect())
+        .unwrap_or_default()
+}
+
+pub fn is_trusted(trusted: &[String], file: &Path, s: &str) -> bool {
+    trusted.contains(&entry(file, s))
+}
+
+/// Returns the `.megaprompt.toml` of `dir`, relative to the working directory
+fn dir_file(dir: &Path) -> Result<PathBuf, String> {
+    let dir = env::current_dir()
+        .map(|cwd| cwd.join(dir))
+        .and_then(|d| d.canonicalize())
+        .map_err(|e| format!("{}: {}", dir.display(), e))?;
+    Ok(dir.join(DIR_FILE))
+}
+
+/// Rewrites the trust list, leaving out the entries of `file` and adding
+/// `add`
+fn update(file: &Path, add: Option<String>) -> Result<(), String> {
+    let list = list_path().ok_or("unable to find the config directory")?;
+    let suffix = format!(" {}", file.display());
+    let mut lines = trusted()
+        .into_iter()
+        .filter(|l| !l.ends_with(&suffix))
+        .collect::<Vec<_>>();
+    lines.extend(add);
+
+    let write = || {
+        if let Some(parent) = list.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&list, lines.join("\n") + "\n")
+    };
+    write().map_err(|e| format!("unable to write {}: {}", list.display(), e))
+}
+
+/// Trusts the `.megaprompt.toml` of `dir` as it is now, after checking that
+/// it is valid
+pub fn trust(dir: &Path) -> Result<PathBuf, String> {
+    let file = dir_file(dir)?;
+    let s = fs::read_to_string(&file)
+        .map_err(|e| format!("unable to read {}: {}", file.display(), e))?;
+    Config::default()
+        .merge(&s)
+        .map_err(|e| format!("{}: {}", file.display(), e))?;
+
+    update(&file, Some(entry(&file, &s)))?;
+    Ok(file)
+}
+
+/// Stops using the `.megaprompt.toml` of `dir`
+pub fn revoke(dir: &Path) -> Result<PathBuf, String> {
+    let file = dir_file(dir)?;
+    update(&file, None)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trusts_contents() {
+        let file = Path::new("/src/project/.megaprompt.toml");
+        let trusted = vec![entry(file, "theme = \"mono\"\n")];
+        assert!(is_trusted(&trusted, file, "theme = \"mono\"\n"));
+        assert!(!is_trusted(&trusted, file, "theme = \"solarized\"\n"));
+        assert!(!is_trusted(
+            &trusted,
+            Path::new("/src/other/.megaprompt.toml"),
+            "theme = \"mono\"\n"
+        ));
+    }
+}
@@ -0,0 +1,5 @@
+#!/bin/sh
+# Counts how often it ran in $COUNT_FILE
+echo run >> "$COUNT_FILE"
+n=$(wc -l < "$COUNT_FILE")
+printf '{"lines": [{"boxes": [{"text": "run %d"}]}]}\n' "$n"
@@ -0,0 +1,7 @@
+#!/bin/sh
+# Prints its output, closes stdout and keeps a child running
+sleep 10 > /dev/null &
+echo $! > "$PID_FILE"
+echo '{"lines": []}'
+exec > /dev/null
+wait
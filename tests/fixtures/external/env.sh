@@ -0,0 +1,3 @@
+#!/bin/sh
+# Shows which variables it got
+printf '{"lines": [{"boxes": [{"text": "GREETING=%s HOME=%s"}]}]}\n' "${GREETING-unset}" "${HOME-unset}"
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Greets with $GREETING, and says how fast it was asked to be
+case "$(cat)" in
+    *'"speed":"slow"'*) speed=slow ;;
+    *) speed=fast ;;
+esac
+cat <<JSON
+{"lines": [{"level": 0, "type": "boxed",
+            "boxes": [{"text": "$GREETING from $speed", "color": "green", "bold": true}]},
+           {"level": 1, "type": "free", "boxes": [{"text": "second"}]}],
+ "right": [{"text": "right", "role": "label"}]}
+JSON
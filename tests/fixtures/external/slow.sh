@@ -0,0 +1,3 @@
+#!/bin/sh
+# Takes far longer than any budget
+exec sleep 10